@@ -153,6 +153,15 @@ impl AdvertisementData {
         ad_data
     }
 
+    pub fn local_name(&self) -> Option<&str> {
+        let (_, name) = self.other_data.iter().find(|(ad_type, _)| {
+            *ad_type == AdvertisementType::CompleteLocalName as u8
+                || *ad_type == AdvertisementType::ShortenedLocalName as u8
+        })?;
+        let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        core::str::from_utf8(&name[..len]).ok()
+    }
+
     pub fn is_advertising_service(&self, uuid: impl Into<ServiceUuid>) -> bool {
         match uuid.into() {
             ServiceUuid::Uuid16(uuid_16)   => self.service_uuids_16.contains(&uuid_16),
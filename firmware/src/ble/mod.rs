@@ -0,0 +1,344 @@
+extern crate alloc;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+mod advertisement;
+mod scanner;
+mod transport;
+
+use esp_println as _;
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::timer::timg::Timer as EspTimer;
+use esp_hal::peripherals::{RNG, RADIO_CLK, BT};
+use bt_hci::param::{AddrKind, BdAddr};
+use bt_hci::controller::ExternalController;
+use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
+use core::cell::Cell;
+use core::fmt::Write;
+use embassy_futures::select::select;
+use embassy_futures::join::{join3,join};
+use embassy_futures::select::Either::{First, Second};
+use embassy_sync::mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::channel::Sender;
+use esp_backtrace as _;
+use trouble_host::scan::{LeAdvReportsIter, Scanner};
+use trouble_host::connection::{PhySet, ScanConfig};
+use trouble_host::{Host, HostResources};
+use trouble_host::prelude::*;
+use trouble_host::Address;
+use esp_wifi::ble::controller::BleConnector;
+use spark_protocol::message as spark_message;
+use spark_protocol::catalog as spark_catalog;
+use spark_protocol::client::{ClientError, SparkClient};
+use advertisement::AdvertisementData;
+
+// Max number of connections
+const CONNECTIONS_MAX: usize = 6;
+const L2CAP_CHANNELS_MAX: usize = 6;
+
+// We need to send this characteristic UUID 0x1 and 0x0 to get notifications??
+pub const MYSTERY_VALUES      : [u8; 2] = [0x1, 0x2];
+
+pub const SPARK_SERVICE_UUID  : u16 = 0xFFC0;
+pub const WRITE_CHARACTERISTIC: u16 = 0xFFC1;
+pub const NOTIF_CHARACTERISTIC: u16 = 0xFFC2;
+
+// How long to wait for the amp to answer a request, and how often to resend it
+const REQUEST_TIMEOUT_MS: u64 = 1000;
+const REQUEST_RETRIES   : u8  = 2;
+
+#[embassy_executor::task]
+pub async fn run(
+    timer: EspTimer<'static>,
+    rng: RNG<'static>,
+    clk: RADIO_CLK<'static>,
+    bt: BT<'static>,
+    channel: Sender<'static, CriticalSectionRawMutex, arrayvec::ArrayString<40>, 40>,
+) {
+    let s = arrayvec::ArrayString::<40>::from("Initializing...").unwrap();
+    channel.send(s).await;
+    let init = esp_wifi::init(
+        timer,
+        esp_hal::rng::Rng::new(rng),
+        clk,
+    )
+    .unwrap();
+
+    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
+    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
+    let address: Address = Address::random([0xB4, 0xE6, 0x2D, 0xB2, 0x1B, 0x36]);
+    let connector = BleConnector::new(&init, bt);
+    let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> = HostResources::new();
+    let controller: ExternalController<_, 20> = ExternalController::new(connector);
+    let stack = trouble_host::new(controller, &mut resources).set_random_address(address);
+
+    let Host {
+        central, mut runner, ..
+    } = stack.build();
+
+    let handler = scanner::ScanHandler::new();
+    let s = arrayvec::ArrayString::<40>::from("Scanning...").unwrap();
+    channel.send(s).await;
+
+    let mut scanner = Scanner::new(central);
+    let addr = select(runner.run_with_handler(&handler), async {
+        while !handler.found_device() {
+            let mut config = ScanConfig::default();
+            config.active = true;
+            config.phys = PhySet::M1;
+            config.interval = Duration::from_secs(1);
+            config.window = Duration::from_secs(1);
+            let mut _session = scanner.scan(&config).await.unwrap();
+        }
+
+        handler.get_device().unwrap()
+    })
+    .await;
+
+
+    let (addr_kind, addr) = match addr {
+        First(a) => todo!(),
+        Second(a) => Some(a).unwrap(),
+    };
+
+    let s = arrayvec::ArrayString::<40>::from("Found amp").unwrap();
+    channel.send(s).await;
+    defmt::info!("addr_kind: {:?} addr: {:?}", addr_kind, addr);
+
+    let config = ConnectConfig {
+        connect_params: ConnectParams {
+            min_connection_interval: Duration::from_micros(7500),
+            max_connection_interval: Duration::from_micros(7500),
+            max_latency: 500,
+            supervision_timeout: Duration::from_secs(10),
+            ..Default::default()
+        },
+        scan_config: ScanConfig {
+            filter_accept_list: &[(addr_kind, &addr)],
+            ..Default::default()
+        },
+    };
+
+    Timer::after(Duration::from_secs(10)).await;
+
+    let Host {
+        mut central, mut runner, ..
+    } = stack.build();
+
+    defmt::info!("Scanning for peripheral...");
+    Timer::after(Duration::from_secs(5)).await;
+
+    let s = arrayvec::ArrayString::<40>::from("Connecting...").unwrap();
+    channel.send(s).await;
+
+    let _ = join(runner.run(), async {
+        defmt::info!("Connecting");
+
+        let mut conn = None;
+        let mut connected = false;
+        while !connected {
+            defmt::info!("Try connect");
+            let resp = central.connect(&config).await;
+            defmt::info!("after");
+            match resp {
+                Ok(c) => {
+                    defmt::info!("Connected!");
+                    connected = true;
+                    conn = Some(c);
+                },
+                Err(a) => {
+                    defmt::info!("Retry");
+                    Timer::after(Duration::from_secs(1)).await;
+                },
+            }
+        }
+
+        let conn_ref = conn.as_ref().expect("Connection not established");
+
+        let client = GattClient::<_, DefaultPacketPool, 10>::new(&stack, &conn_ref)
+            .await
+            .unwrap();
+
+        let s = arrayvec::ArrayString::<40>::from("Connected!").unwrap();
+        channel.send(s).await;
+
+        let _ = join(client.task(), async {
+            let services = client.services_by_uuid(&Uuid::new_short(SPARK_SERVICE_UUID)).await.unwrap();
+            let service = services.first().unwrap().clone();
+
+            let read_characteristic: Characteristic<u8> = client
+                .characteristic_by_uuid(&service, &Uuid::from(NOTIF_CHARACTERISTIC))
+                .await
+                .unwrap();
+
+
+            let res = client.write_characteristic(&read_characteristic, &MYSTERY_VALUES).await;
+
+            let write_characteristic: Characteristic<u8> = client
+                .characteristic_by_uuid(&service, &Uuid::new_short(WRITE_CHARACTERISTIC))
+                .await
+                .unwrap();
+
+
+            let listener = client.subscribe(&read_characteristic, false).await.unwrap();
+            let transport = transport::BleTransport::new(conn_ref, &client, &write_characteristic, listener);
+            let spark = SparkClient::new(transport, handler.get_model(), REQUEST_TIMEOUT_MS, REQUEST_RETRIES);
+            let hardware_preset = Cell::new(0u8);
+
+            // Logs a failed request, and shows the amp dropping out on the display
+            let report = |e: ClientError<_>| async move {
+                defmt::warn!("Request failed: {}", defmt::Debug2Format(&e));
+                if matches!(e, ClientError::TimedOut) {
+                    let s = arrayvec::ArrayString::<40>::from("Amp not\nresponding").unwrap();
+                    channel.send(s).await;
+                }
+            };
+
+            // Receives and times requests until the link drops or fails.
+            // Everything else stops with it.
+            let link = async {
+                let result = match select(spark.run(), async {
+                    let mut stats = spark.diagnostics();
+                    loop {
+                        Timer::after(Duration::from_millis(100)).await;
+                        if let Err(e) = spark.tick(Instant::now().as_millis()).await {
+                            return Err(e);
+                        }
+
+                        if spark.diagnostics() != stats {
+                            stats = spark.diagnostics();
+                            defmt::warn!(
+                                "Decode errors: {} checksum errors, {} other errors, {} messages",
+                                stats.checksum_errors, stats.other_errors, stats.messages,
+                            );
+                        }
+                    }
+                })
+                .await {
+                    First(result) => result,
+                    Second(result) => result,
+                };
+
+                match result {
+                    Ok(()) => defmt::info!("Amp disconnected"),
+                    Err(e) => report(e).await,
+                }
+                let s = arrayvec::ArrayString::<40>::from("Amp\ndisconnected").unwrap();
+                channel.send(s).await;
+            };
+
+            let _ = select(link, join3(
+                async {
+                    match spark.amp_name().await {
+                        Ok(name) => {
+                            defmt::info!("Connected to {}", name.as_str());
+                            let s = arrayvec::ArrayString::<40>::from(&name).unwrap();
+                            channel.send(s).await;
+                        },
+                        Err(e) => report(e).await,
+                    }
+                    match spark.serial_number().await {
+                        Ok(serial) => defmt::info!("Serial number {}", serial.as_str()),
+                        Err(e) => report(e).await,
+                    }
+                    match spark.firmware_version().await {
+                        Ok(version) => defmt::info!("Firmware {}.{}.{}.{}", version.major, version.minor, version.patch, version.build),
+                        Err(e) => report(e).await,
+                    }
+                    match spark.preset_checksums().await {
+                        Ok(checksums) => defmt::info!("{} hardware presets", checksums.len()),
+                        Err(e) => report(e).await,
+                    }
+                    match spark.selected_hardware_preset().await {
+                        Ok(preset) => {
+                            defmt::info!("Amp on hardware preset {}", preset);
+                            hardware_preset.set(preset);
+                            let mut s = arrayvec::ArrayString::<40>::new();
+                            let _ = write!(s, "Hardware\npreset: {}", preset);
+                            channel.send(s).await;
+                        },
+                        Err(e) => report(e).await,
+                    }
+                    match spark.current_preset().await {
+                        Ok(preset) => {
+                            defmt::info!("Preset {}: {}", preset.slot, preset.name.as_str());
+                            let mut s = arrayvec::ArrayString::<40>::new();
+                            for c in preset.name.chars() {
+                                if s.try_push(c).is_err() { break; }
+                            }
+                            channel.send(s).await;
+                        },
+                        Err(e) => report(e).await,
+                    }
+                },
+                async {
+                    let mut events = spark.subscribe_events();
+                    loop {
+                        match events.next().await {
+                            spark_message::SparkToAppMsg::HardwarePresetChanged { sequence, preset } => {
+                                defmt::info!("Amp on hardware preset {}, seq: {}", preset, sequence);
+                                hardware_preset.set(preset);
+                                let mut s = arrayvec::ArrayString::<40>::new();
+                                let _ = write!(s, "Hardware\npreset: {}", preset);
+                                channel.send(s).await;
+                            },
+                            spark_message::SparkToAppMsg::ParameterChanged { effect_id, param_index, value, .. } => {
+                                let mut s = arrayvec::ArrayString::<40>::new();
+                                match spark_catalog::find(&effect_id) {
+                                    Some(effect) => {
+                                        let _ = write!(s, "{}\n", effect.name);
+                                        match effect.param(param_index) {
+                                            Some(p) => match p.choice(value) {
+                                                Some(choice) => { let _ = write!(s, "{}: {}", p.name, choice); },
+                                                None => { let _ = write!(s, "{}: {:.1}{}", p.name, p.display_value(value), p.unit.suffix()); },
+                                            },
+                                            None => { let _ = write!(s, "{}: {:.2}", param_index, value); },
+                                        }
+                                    },
+                                    None => { let _ = write!(s, "Param {}: {:.2}", param_index, value); },
+                                }
+                                channel.send(s).await;
+                            },
+                            spark_message::SparkToAppMsg::TunerReading { note, offset, .. } => {
+                                let name = spark_message::NOTE_NAMES.get(note as usize).unwrap_or(&"-");
+                                let mut s = arrayvec::ArrayString::<40>::new();
+                                let _ = write!(s, "Tuner: {}\n{:+.0} cents", name, spark_message::tuner_cents(offset));
+                                channel.send(s).await;
+                            },
+                            spark_message::SparkToAppMsg::Unknown { sequence, command, sub_command, payload } => {
+                                defmt::info!("Unhandled {:X} {:X}, seq: {}\n{:X}", command, sub_command, sequence, payload.as_slice());
+                            },
+                            _ => {}
+                        }
+                    }
+                },
+                async {
+                    Timer::after(Duration::from_secs(4)).await;
+                    loop {
+                        // Carry on from wherever the amp is now
+                        let preset = hardware_preset.get() % spark.profile().hardware_presets + 1;
+                        hardware_preset.set(preset);
+
+                        let mut s = arrayvec::ArrayString::<40>::new();
+                        let _ = write!(s, "Set Hardware\npreset: {}", preset);
+                        channel.send(s).await;
+                        if let Err(e) = spark.select_hardware_preset(preset).await {
+                            report(e).await;
+                        }
+                        Timer::after(Duration::from_secs(2)).await;
+                    }
+                },
+            ))
+            .await;
+        })
+        .await;
+    })
+    .await;
+
+    loop {
+        defmt::info!("BLE loop");
+        Timer::after(Duration::from_secs(10)).await;
+    }
+}
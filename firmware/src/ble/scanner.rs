@@ -1,7 +1,7 @@
 use defmt;
 use bt_hci::param::{AddrKind, BdAddr};
 use bt_hci::controller::ExternalController;
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use embassy_futures::select::select;
 use embassy_futures::select::Either::Second;
 use embassy_time::{Duration, Timer};
@@ -14,17 +14,20 @@ use trouble_host::prelude::*;
 use trouble_host::Address;
 use esp_wifi::ble::controller::BleConnector;
 use super::advertisement::AdvertisementData;
+use spark_protocol::amp::AmpModel;
 
 use super::SPARK_SERVICE_UUID;
 
 pub struct ScanHandler {
-    device: RefCell<Option<(AddrKind, BdAddr)>>
+    device: RefCell<Option<(AddrKind, BdAddr)>>,
+    model: Cell<AmpModel>,
 }
 
 impl ScanHandler {
     pub fn new() -> Self {
         Self {
-            device: RefCell::new(None)
+            device: RefCell::new(None),
+            model: Cell::new(AmpModel::Unknown),
         }
     }
 
@@ -32,6 +35,11 @@ impl ScanHandler {
         self.device.borrow().clone()
     }
 
+    // Model guessed from the advertised name, refined later by GetAmpName
+    pub fn get_model(&self) -> AmpModel {
+        self.model.get()
+    }
+
     pub fn found_device(&self) -> bool {
         self.get_device().is_some()
     }
@@ -46,6 +54,9 @@ impl EventHandler for ScanHandler {
                 // defmt::info!("Found address {:?} advertising {:02X?}", report.addr, SPARK_SERVICE_UUID);
                 let mut device = self.device.borrow_mut();
                 *device = Some((report.addr_kind, report.addr));
+                if let Some(name) = ad.local_name() {
+                    self.model.set(AmpModel::from_name(name));
+                }
             }
         }
     }
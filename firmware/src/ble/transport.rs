@@ -0,0 +1,54 @@
+use bt_hci::controller::Controller;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use trouble_host::prelude::*;
+use trouble_host::BleHostError;
+use spark_protocol::transport::{LinkState, SparkTransport};
+
+// The amp's GATT link. Blocks are written to the write characteristic and
+// bytes arrive as notifications on the notify characteristic.
+pub struct BleTransport<'a, 'c, C: Controller, const MAX_SERVICES: usize> {
+    conn:     &'a Connection<'c, DefaultPacketPool>,
+    client:   &'a GattClient<'c, C, DefaultPacketPool, MAX_SERVICES>,
+    write:    &'a Characteristic<u8>,
+    // Behind a mutex so receive can take &self like send
+    listener: Mutex<NoopRawMutex, NotificationListener<'a, 512>>,
+}
+
+impl<'a, 'c, C: Controller, const MAX_SERVICES: usize> BleTransport<'a, 'c, C, MAX_SERVICES> {
+    pub fn new(
+        conn: &'a Connection<'c, DefaultPacketPool>,
+        client: &'a GattClient<'c, C, DefaultPacketPool, MAX_SERVICES>,
+        write: &'a Characteristic<u8>,
+        listener: NotificationListener<'a, 512>,
+    ) -> Self {
+        BleTransport { conn, client, write, listener: Mutex::new(listener) }
+    }
+}
+
+impl<C: Controller, const MAX_SERVICES: usize> SparkTransport for BleTransport<'_, '_, C, MAX_SERVICES> {
+    type Error = BleHostError<C::Error>;
+
+    async fn send(&self, block: &[u8]) -> Result<(), Self::Error> {
+        defmt::info!("write characteristic\n{:X}", block);
+        self.client.write_characteristic(self.write, block).await
+    }
+
+    // One notification per call, anything past the end of `buf` is dropped
+    async fn receive(&self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let data = self.listener.lock().await.next().await;
+        let data = data.as_ref();
+        defmt::info!("Got notification:\n{:X}", data);
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn link_state(&self) -> LinkState {
+        if self.conn.is_connected() {
+            LinkState::Connected
+        } else {
+            LinkState::Disconnected
+        }
+    }
+}
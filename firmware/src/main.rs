@@ -17,7 +17,6 @@ use defmt;
 
 mod ble;
 mod display;
-mod spark_message;
 
 pub type DisplayString = arrayvec::ArrayString<40>;
 static CHANNEL: Channel<CriticalSectionRawMutex, DisplayString, 40> = Channel::new();
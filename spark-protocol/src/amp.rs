@@ -0,0 +1,78 @@
+use crate::message::{AppToSparkMsg, CURRENT_PRESET_SLOT};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmpModel {
+    Spark40,
+    SparkMini,
+    SparkGo,
+    Spark2,
+    Unknown,
+}
+
+// What a model supports. Unknown amps get the Spark 40 profile, which is what
+// everything assumed before models were detected. The amp's own count of
+// hardware presets, from its PresetChecksums reply, replaces the model's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AmpProfile {
+    pub hardware_presets: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    // Hardware preset outside 1..=hardware_presets, or a preset slot outside
+    // 0..hardware_presets
+    InvalidPresetSlot(u8),
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.as_bytes()
+        .windows(needle.len())
+        .any(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+impl AmpModel {
+    // Works on both the advertised BLE name ("Spark 40 Audio", "Spark MINI BLE")
+    // and the amp's reply to GetAmpName.
+    pub fn from_name(name: &str) -> Self {
+        if contains_ignore_case(name, "MINI") {
+            AmpModel::SparkMini
+        } else if contains_ignore_case(name, "SPARK GO") {
+            AmpModel::SparkGo
+        } else if contains_ignore_case(name, "SPARK 2") {
+            AmpModel::Spark2
+        } else if contains_ignore_case(name, "40") {
+            AmpModel::Spark40
+        } else {
+            AmpModel::Unknown
+        }
+    }
+
+    pub fn profile(&self) -> AmpProfile {
+        match self {
+            AmpModel::Spark40
+            | AmpModel::SparkMini
+            | AmpModel::SparkGo
+            | AmpModel::Unknown => AmpProfile { hardware_presets: 4 },
+            AmpModel::Spark2 => AmpProfile { hardware_presets: 8 },
+        }
+    }
+}
+
+impl AmpProfile {
+    // Checks a command against this model before it is encoded
+    pub fn validate(&self, msg: &AppToSparkMsg) -> Result<(), CommandError> {
+        match msg {
+            AppToSparkMsg::SetHardwarePreset(preset)
+                if *preset == 0 || *preset > self.hardware_presets =>
+            {
+                Err(CommandError::InvalidPresetSlot(*preset))
+            }
+            AppToSparkMsg::SendPreset(preset)
+                if preset.slot != CURRENT_PRESET_SLOT && preset.slot >= self.hardware_presets =>
+            {
+                Err(CommandError::InvalidPresetSlot(preset.slot))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,362 @@
+// Built-in table of the effect and amp models the Spark knows about, keyed by
+// the model id used on the wire ("DistortionTS9", "RolandJC120", ...).
+//
+// Parameters travel as 0.0..=1.0; min/max give the range the amp's own UI
+// shows for them, so a value can be displayed as e.g. "Level: 62%". Switches
+// spread their positions evenly over the same range instead.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Gate,
+    Comp,
+    Drive,
+    Amp,
+    Mod,
+    Delay,
+    Reverb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    None,
+    Percent,
+    Ms,
+    Db,
+    Hz,
+}
+
+#[derive(Debug)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub min:  f32,
+    pub max:  f32,
+    pub unit: Unit,
+    // Position names for a switch, empty for a continuous parameter
+    pub choices: &'static [&'static str],
+}
+
+#[derive(Debug)]
+pub struct EffectInfo {
+    pub id:       &'static str,
+    pub name:     &'static str,
+    pub category: Category,
+    pub params:   &'static [ParamInfo],
+}
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Gate   => "Gate",
+            Category::Comp   => "Comp",
+            Category::Drive  => "Drive",
+            Category::Amp    => "Amp",
+            Category::Mod    => "Mod",
+            Category::Delay  => "Delay",
+            Category::Reverb => "Reverb",
+        }
+    }
+}
+
+impl Unit {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Unit::None    => "",
+            Unit::Percent => "%",
+            Unit::Ms      => "ms",
+            Unit::Db      => "dB",
+            Unit::Hz      => "Hz",
+        }
+    }
+}
+
+impl ParamInfo {
+    // Maps a normalised 0.0..=1.0 value onto the displayed range. A switch
+    // gives the index of its nearest position.
+    pub fn display_value(&self, value: f32) -> f32 {
+        let scaled = self.min + value.clamp(0.0, 1.0) * (self.max - self.min);
+        // Switches start at 0, so truncating rounds
+        if self.choices.is_empty() { scaled } else { (scaled + 0.5) as u32 as f32 }
+    }
+
+    // Name of the position a switch is in, None for a continuous parameter
+    pub fn choice(&self, value: f32) -> Option<&'static str> {
+        self.choices.get(self.display_value(value) as usize).copied()
+    }
+}
+
+impl EffectInfo {
+    pub fn param(&self, index: u8) -> Option<&'static ParamInfo> {
+        self.params.get(index as usize)
+    }
+}
+
+pub fn find(id: &str) -> Option<&'static EffectInfo> {
+    EFFECTS.iter().find(|e| e.id == id)
+}
+
+const fn knob(name: &'static str) -> ParamInfo {
+    ParamInfo { name, min: 0.0, max: 10.0, unit: Unit::None, choices: &[] }
+}
+
+const fn percent(name: &'static str) -> ParamInfo {
+    ParamInfo { name, min: 0.0, max: 100.0, unit: Unit::Percent, choices: &[] }
+}
+
+const fn param(name: &'static str, min: f32, max: f32, unit: Unit) -> ParamInfo {
+    ParamInfo { name, min, max, unit, choices: &[] }
+}
+
+const fn switch(name: &'static str, choices: &'static [&'static str]) -> ParamInfo {
+    ParamInfo { name, min: 0.0, max: (choices.len() - 1) as f32, unit: Unit::None, choices }
+}
+
+// Delays can follow the preset's tempo instead of their own time
+const BPM: ParamInfo = switch("BPM", &["Off", "On"]);
+
+const AMP_PARAMS: &[ParamInfo] = &[
+    knob("Gain"),
+    knob("Bass"),
+    knob("Mid"),
+    knob("Treble"),
+    knob("Master"),
+];
+
+const fn amp(id: &'static str, name: &'static str) -> EffectInfo {
+    EffectInfo { id, name, category: Category::Amp, params: AMP_PARAMS }
+}
+
+pub static EFFECTS: &[EffectInfo] = &[
+    // Gate
+    EffectInfo { id: "bias.noisegate", name: "Noise Gate", category: Category::Gate, params: &[
+        param("Threshold", -90.0, 0.0, Unit::Db),
+        param("Decay", 0.0, 1000.0, Unit::Ms),
+    ]},
+
+    // Comp
+    EffectInfo { id: "LA2AComp", name: "LA Comp", category: Category::Comp, params: &[
+        knob("Limit/Comp"),
+        knob("Gain"),
+        knob("Peak Reduction"),
+    ]},
+    EffectInfo { id: "BlueComp", name: "Sustain Comp", category: Category::Comp, params: &[
+        knob("Level"),
+        knob("Tone"),
+        knob("Attack"),
+        knob("Sustain"),
+    ]},
+    EffectInfo { id: "Compressor", name: "Red Comp", category: Category::Comp, params: &[
+        knob("Output"),
+        knob("Sensitivity"),
+    ]},
+    EffectInfo { id: "BBEOpticalComp", name: "Optical Comp", category: Category::Comp, params: &[
+        knob("Volume"),
+        knob("Comp"),
+        knob("Pad"),
+    ]},
+
+    // Drive
+    EffectInfo { id: "Booster", name: "Booster", category: Category::Drive, params: &[
+        param("Gain", 0.0, 20.0, Unit::Db),
+    ]},
+    EffectInfo { id: "DistortionTS9", name: "Tube Drive", category: Category::Drive, params: &[
+        knob("Overdrive"),
+        knob("Tone"),
+        knob("Level"),
+    ]},
+    EffectInfo { id: "Overdrive", name: "Over Drive", category: Category::Drive, params: &[
+        knob("Level"),
+        knob("Tone"),
+        knob("Drive"),
+    ]},
+    EffectInfo { id: "Fuzz", name: "Fuzz Face", category: Category::Drive, params: &[
+        knob("Volume"),
+        knob("Fuzz"),
+    ]},
+    EffectInfo { id: "ProCoRat", name: "Rat Distortion", category: Category::Drive, params: &[
+        knob("Distortion"),
+        knob("Filter"),
+        knob("Volume"),
+    ]},
+    EffectInfo { id: "BassBigMuff", name: "Bass Muff", category: Category::Drive, params: &[
+        knob("Volume"),
+        knob("Tone"),
+        knob("Sustain"),
+    ]},
+    EffectInfo { id: "GuitarMuff", name: "Guitar Muff", category: Category::Drive, params: &[
+        knob("Volume"),
+        knob("Tone"),
+        knob("Sustain"),
+    ]},
+    EffectInfo { id: "MaestroBassmaster", name: "Bassmaster", category: Category::Drive, params: &[
+        knob("Brass Volume"),
+        knob("Sensitivity"),
+        knob("Bass Volume"),
+    ]},
+    EffectInfo { id: "SABdriver", name: "SAB Driver", category: Category::Drive, params: &[
+        knob("Volume"),
+        knob("Tone"),
+        knob("Drive"),
+        knob("Boost"),
+    ]},
+    EffectInfo { id: "KlonCentaurSilver", name: "Centaur", category: Category::Drive, params: &[
+        knob("Gain"),
+        knob("Treble"),
+        knob("Output"),
+    ]},
+
+    // Amp
+    amp("RolandJC120", "Silver 120"),
+    amp("Twin", "Black Duo"),
+    amp("ADClean", "AD Clean"),
+    amp("94MatchDCV2", "Match DC"),
+    amp("Bassman", "Tweed Bass"),
+    amp("AC Boost", "AC Boost"),
+    amp("Checkmate", "Checkmate"),
+    amp("TwoStoneSP50", "Two Stone SP50"),
+    amp("Deluxe65", "Deluxe 65"),
+    amp("Plexi", "Plexiglas"),
+    amp("OverDrivenJM45", "JM45"),
+    amp("OverDrivenLuxVerb", "Lux Verb"),
+    amp("Bogner", "RB 101"),
+    amp("OrangeAD30", "British 30"),
+    amp("AmericanHighGain", "American High Gain"),
+    amp("SLO100", "SLO 100"),
+    amp("YJM100", "YJM100"),
+    amp("Rectifier", "Treadplate"),
+    amp("EVH", "Insane"),
+    amp("SwitchAxeLead", "Switch Axe"),
+    amp("Invader", "Rocker V"),
+    amp("BE101", "BE 101"),
+    amp("Acoustic", "Acoustic"),
+    amp("AcousticAmpV2", "Acoustic Amp"),
+    amp("FatAcousticV2", "Fat Acoustic"),
+    amp("FlatAcoustic", "Flat Acoustic"),
+    amp("GK800", "RB-800"),
+    amp("Sunny3000", "Sunny 3000"),
+    amp("W600", "W600"),
+    amp("Hammer500", "Hammer 500"),
+
+    // Mod
+    EffectInfo { id: "Tremolo", name: "Tremolo", category: Category::Mod, params: &[
+        param("Speed", 0.5, 10.0, Unit::Hz),
+        percent("Depth"),
+        knob("Level"),
+    ]},
+    EffectInfo { id: "ChorusAnalog", name: "Chorus", category: Category::Mod, params: &[
+        percent("E.Level"),
+        param("Rate", 0.1, 10.0, Unit::Hz),
+        percent("Depth"),
+        knob("Tone"),
+    ]},
+    EffectInfo { id: "Flanger", name: "Flanger", category: Category::Mod, params: &[
+        param("Rate", 0.1, 10.0, Unit::Hz),
+        percent("Depth"),
+        percent("Feedback"),
+    ]},
+    EffectInfo { id: "Phaser", name: "Phaser", category: Category::Mod, params: &[
+        param("Speed", 0.1, 10.0, Unit::Hz),
+        percent("Intensity"),
+    ]},
+    EffectInfo { id: "Vibrato01", name: "Vibrato", category: Category::Mod, params: &[
+        param("Speed", 0.5, 10.0, Unit::Hz),
+        percent("Depth"),
+    ]},
+    EffectInfo { id: "UniVibe", name: "Vibe", category: Category::Mod, params: &[
+        param("Speed", 0.5, 10.0, Unit::Hz),
+        knob("Vibrato"),
+        percent("Intensity"),
+    ]},
+    EffectInfo { id: "Cloner", name: "Cloner Chorus", category: Category::Mod, params: &[
+        param("Rate", 0.1, 10.0, Unit::Hz),
+        knob("Depth"),
+    ]},
+    EffectInfo { id: "MiniVibe", name: "Classic Vibe", category: Category::Mod, params: &[
+        param("Speed", 0.5, 10.0, Unit::Hz),
+        percent("Intensity"),
+    ]},
+    EffectInfo { id: "Tremolator", name: "Tremolator", category: Category::Mod, params: &[
+        percent("Depth"),
+        param("Speed", 0.5, 10.0, Unit::Hz),
+        knob("Level"),
+    ]},
+    EffectInfo { id: "TremoloSquare", name: "Optical Tremolo", category: Category::Mod, params: &[
+        param("Speed", 0.5, 10.0, Unit::Hz),
+        percent("Depth"),
+        knob("Level"),
+    ]},
+    EffectInfo { id: "GuitarEQ6", name: "Guitar EQ", category: Category::Mod, params: &[
+        param("Level", -12.0, 12.0, Unit::Db),
+        param("100", -12.0, 12.0, Unit::Db),
+        param("200", -12.0, 12.0, Unit::Db),
+        param("400", -12.0, 12.0, Unit::Db),
+        param("800", -12.0, 12.0, Unit::Db),
+        param("1.6k", -12.0, 12.0, Unit::Db),
+        param("3.2k", -12.0, 12.0, Unit::Db),
+    ]},
+    EffectInfo { id: "BassEQ6", name: "Bass EQ", category: Category::Mod, params: &[
+        param("Level", -12.0, 12.0, Unit::Db),
+        param("50", -12.0, 12.0, Unit::Db),
+        param("120", -12.0, 12.0, Unit::Db),
+        param("400", -12.0, 12.0, Unit::Db),
+        param("800", -12.0, 12.0, Unit::Db),
+        param("4.5k", -12.0, 12.0, Unit::Db),
+        param("10k", -12.0, 12.0, Unit::Db),
+    ]},
+
+    // Delay
+    EffectInfo { id: "DelayMono", name: "Digital Delay", category: Category::Delay, params: &[
+        percent("E.Level"),
+        percent("Feedback"),
+        param("Delay Time", 0.0, 1000.0, Unit::Ms),
+        switch("Mode", &["Mono", "Stereo"]),
+        BPM,
+    ]},
+    EffectInfo { id: "DelayEchoFilt", name: "Echo Filter", category: Category::Delay, params: &[
+        percent("Level"),
+        percent("Feedback"),
+        param("Delay Time", 0.0, 1000.0, Unit::Ms),
+        knob("Tone"),
+        BPM,
+    ]},
+    EffectInfo { id: "VintageDelay", name: "Vintage Delay", category: Category::Delay, params: &[
+        param("Repeat Rate", 0.0, 1000.0, Unit::Ms),
+        percent("Intensity"),
+        percent("Echo"),
+        BPM,
+    ]},
+    EffectInfo { id: "DelayReverse", name: "Reverse Delay", category: Category::Delay, params: &[
+        percent("Mix"),
+        percent("Decay"),
+        knob("Filter"),
+        param("Time", 0.0, 2000.0, Unit::Ms),
+        BPM,
+    ]},
+    EffectInfo { id: "DelayMultiHead", name: "Multi Head", category: Category::Delay, params: &[
+        param("Repeat Rate", 0.0, 1000.0, Unit::Ms),
+        percent("Intensity"),
+        percent("Echo Vol"),
+        switch("Mode", &["1", "2", "3", "4"]),
+        BPM,
+    ]},
+    EffectInfo { id: "DelayRe201", name: "Echo Tape", category: Category::Delay, params: &[
+        percent("Sustain"),
+        percent("Volume"),
+        knob("Tone"),
+        param("Time", 0.0, 1000.0, Unit::Ms),
+        BPM,
+    ]},
+
+    // Reverb
+    EffectInfo { id: "bias.reverb", name: "Reverb", category: Category::Reverb, params: &[
+        percent("Level"),
+        percent("Damping"),
+        percent("Dwell"),
+        param("Time", 0.0, 10000.0, Unit::Ms),
+        param("Low Cut", 20.0, 1000.0, Unit::Hz),
+        param("High Cut", 1000.0, 20000.0, Unit::Hz),
+        switch("Type", &[
+            "Room Studio A", "Room Studio B", "Chamber", "Hall Natural", "Hall Medium",
+            "Hall Ambient", "Plate Short", "Plate Rich", "Plate Long",
+        ]),
+    ]},
+];
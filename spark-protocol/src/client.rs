@@ -0,0 +1,337 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+use crate::amp::{AmpModel, AmpProfile, CommandError};
+use crate::message::{AppToSparkMsg, DecoderDiagnostics, EncodeError, FirmwareVersion, Preset, SparkMsgEncoder, SparkToAppMsg};
+use crate::stream::SparkStreamDecoder;
+use crate::tracker::{RequestResult, RequestTracker, TrackerEvent};
+use crate::transport::{LinkState, SparkTransport};
+
+// Events kept for a subscriber that isn't keeping up. The oldest are dropped
+// first, so a flood of tuner readings can't use up the heap.
+const MAX_QUEUED_EVENTS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientError<E> {
+    Transport(E),
+    // Checked against the amp's profile and never sent
+    Command(CommandError),
+    // Too long to fit in a message
+    Encode(EncodeError),
+    // No reply after the initial send and every retry
+    TimedOut,
+    // The reply matched the request but wasn't the kind expected
+    UnexpectedReply,
+}
+
+struct ClientState {
+    encoder:    SparkMsgEncoder,
+    tracker:    RequestTracker,
+    stream:     SparkStreamDecoder,
+    // Requests sent since the last tick, timed from the next one
+    unstamped:  Vec<u8>,
+    // Finished requests not yet picked up by whoever sent them
+    finished:   Vec<RequestResult>,
+    waiting:    Vec<Waker>,
+    subscribed: bool,
+    events:     VecDeque<SparkToAppMsg>,
+    subscriber: Option<Waker>,
+}
+
+// Talks to an amp over any transport. Each request is sent, tracked and
+// resent as needed, and resolves with the amp's reply, so callers never see
+// blocks, sequence numbers or reply matching.
+//
+// Two things have to be driven alongside the requests: run, which receives
+// and dispatches replies, and tick, which passes time in as milliseconds so
+// this stays independent of the timer.
+pub struct SparkClient<T> {
+    transport:        T,
+    model:            Cell<AmpModel>,
+    // As counted by the amp itself, once preset_checksums has succeeded
+    hardware_presets: Cell<Option<u8>>,
+    state:            RefCell<ClientState>,
+}
+
+impl<T: SparkTransport> SparkClient<T> {
+    // `model` is whatever is known before connecting, usually from the
+    // advertised name. It is updated when amp_name succeeds.
+    pub fn new(transport: T, model: AmpModel, timeout_ms: u64, max_retries: u8) -> Self {
+        SparkClient {
+            transport,
+            model: Cell::new(model),
+            hardware_presets: Cell::new(None),
+            state: RefCell::new(ClientState {
+                encoder:    SparkMsgEncoder::new(),
+                tracker:    RequestTracker::new(timeout_ms, max_retries),
+                stream:     SparkStreamDecoder::new(),
+                unstamped:  Vec::new(),
+                finished:   Vec::new(),
+                waiting:    Vec::new(),
+                subscribed: false,
+                events:     VecDeque::new(),
+                subscriber: None,
+            }),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn model(&self) -> AmpModel {
+        self.model.get()
+    }
+
+    // The model's profile, with the amp's own hardware preset count once known
+    pub fn profile(&self) -> AmpProfile {
+        let mut profile = self.model.get().profile();
+        if let Some(hardware_presets) = self.hardware_presets.get() {
+            profile.hardware_presets = hardware_presets;
+        }
+        profile
+    }
+
+    pub fn diagnostics(&self) -> DecoderDiagnostics {
+        self.state.borrow().stream.diagnostics()
+    }
+
+    // Receives from the amp until the link drops, which ends the run cleanly.
+    // Blocks that fail to decode only show up in diagnostics.
+    pub async fn run(&self) -> Result<(), ClientError<T::Error>> {
+        let mut buf = [0u8; 256];
+        loop {
+            let len = match self.transport.receive(&mut buf).await {
+                Ok(len) => len,
+                Err(_) if self.transport.link_state() == LinkState::Disconnected => return Ok(()),
+                Err(e) => return Err(ClientError::Transport(e)),
+            };
+
+            let mut state = self.state.borrow_mut();
+            state.stream.push(&buf[..len]);
+            while let Some(msg) = state.stream.next_message() {
+                let Ok(msg) = msg else { continue };
+                let result = state.tracker.on_message(&msg);
+                match result {
+                    Some(result) => state.finish(result),
+                    None => state.queue_event(msg),
+                }
+            }
+        }
+    }
+
+    // Resends requests whose timeout has passed and fails those that have
+    // used all their retries. Call it every so often with the current time.
+    pub async fn tick(&self, now_ms: u64) -> Result<(), ClientError<T::Error>> {
+        let events = {
+            let mut state = self.state.borrow_mut();
+            let ClientState { tracker, unstamped, .. } = &mut *state;
+            for sequence in unstamped.drain(..) {
+                tracker.stamp(sequence, now_ms);
+            }
+            tracker.poll(now_ms)
+        };
+
+        for event in events {
+            match event {
+                TrackerEvent::Resend { blocks, .. } => {
+                    for block in &blocks {
+                        self.transport.send(block).await.map_err(ClientError::Transport)?;
+                    }
+                }
+                TrackerEvent::Finished(result) => self.state.borrow_mut().finish(*result),
+            }
+        }
+        Ok(())
+    }
+
+    // Messages the amp sends on its own, like knob turns, preset buttons and
+    // tuner readings. Nothing is kept until this is called, and there should
+    // only be one subscription at a time.
+    pub fn subscribe_events(&self) -> EventSubscription<'_, T> {
+        self.state.borrow_mut().subscribed = true;
+        EventSubscription { client: self }
+    }
+
+    pub async fn amp_name(&self) -> Result<String, ClientError<T::Error>> {
+        match self.request(AppToSparkMsg::GetAmpName).await? {
+            SparkToAppMsg::AmpName { name, .. } => {
+                let model = AmpModel::from_name(&name);
+                if model != AmpModel::Unknown {
+                    self.model.set(model);
+                }
+                Ok(name)
+            }
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    pub async fn serial_number(&self) -> Result<String, ClientError<T::Error>> {
+        match self.request(AppToSparkMsg::GetSerialNumber).await? {
+            SparkToAppMsg::SerialNumber { serial, .. } => Ok(serial),
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    pub async fn firmware_version(&self) -> Result<FirmwareVersion, ClientError<T::Error>> {
+        match self.request(AppToSparkMsg::GetFirmwareVersion).await? {
+            SparkToAppMsg::FirmwareVersion { version, .. } => Ok(version),
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    // Indexed by hardware preset slot, starting at 0. The amp sends one per
+    // slot, which gives the slot count later commands are checked against.
+    pub async fn preset_checksums(&self) -> Result<Vec<u8>, ClientError<T::Error>> {
+        match self.request(AppToSparkMsg::GetPresetChecksums).await? {
+            SparkToAppMsg::PresetChecksums { checksums, .. } => {
+                match u8::try_from(checksums.len()) {
+                    Ok(count) if count > 0 => self.hardware_presets.set(Some(count)),
+                    _ => {},
+                }
+                Ok(checksums)
+            }
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    pub async fn current_preset(&self) -> Result<Preset, ClientError<T::Error>> {
+        match self.request(AppToSparkMsg::GetCurrentPreset).await? {
+            SparkToAppMsg::Preset { preset, .. } => Ok(preset),
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    // Numbered from 1 like select_hardware_preset
+    pub async fn selected_hardware_preset(&self) -> Result<u8, ClientError<T::Error>> {
+        match self.request(AppToSparkMsg::GetSelectedHardwarePreset).await? {
+            SparkToAppMsg::SelectedHardwarePreset { preset, .. } => Ok(preset),
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    pub async fn select_hardware_preset(&self, preset: u8) -> Result<(), ClientError<T::Error>> {
+        self.command(AppToSparkMsg::SetHardwarePreset(preset)).await
+    }
+
+    pub async fn send_preset(&self, preset: Preset) -> Result<(), ClientError<T::Error>> {
+        self.command(AppToSparkMsg::SendPreset(preset)).await
+    }
+
+    pub async fn toggle_effect(&self, effect_id: &str, enabled: bool) -> Result<(), ClientError<T::Error>> {
+        self.command(AppToSparkMsg::ToggleEffect { effect_id: effect_id.into(), enabled }).await
+    }
+
+    pub async fn set_parameter(&self, effect_id: &str, param_index: u8, value: f32) -> Result<(), ClientError<T::Error>> {
+        self.command(AppToSparkMsg::SetParameter { effect_id: effect_id.into(), param_index, value }).await
+    }
+
+    pub async fn change_effect(&self, old_id: &str, new_id: &str) -> Result<(), ClientError<T::Error>> {
+        self.command(AppToSparkMsg::ChangeEffect { old_id: old_id.into(), new_id: new_id.into() }).await
+    }
+
+    pub async fn set_tuner(&self, enabled: bool) -> Result<(), ClientError<T::Error>> {
+        self.command(AppToSparkMsg::SetTuner(enabled)).await
+    }
+
+    // A request the amp answers with an acknowledgement
+    async fn command(&self, msg: AppToSparkMsg) -> Result<(), ClientError<T::Error>> {
+        match self.request(msg).await? {
+            SparkToAppMsg::Ack { .. } => Ok(()),
+            _ => Err(ClientError::UnexpectedReply),
+        }
+    }
+
+    // Sends any request and waits for the reply tracked to it
+    pub async fn request(&self, msg: AppToSparkMsg) -> Result<SparkToAppMsg, ClientError<T::Error>> {
+        self.profile().validate(&msg).map_err(ClientError::Command)?;
+
+        let (sequence, blocks) = {
+            let mut state = self.state.borrow_mut();
+            let sequence = state.encoder.next_sequence();
+            let blocks = state.encoder.encode(msg.clone()).map_err(ClientError::Encode)?;
+            // Sequence numbers wrap, a result nobody collected is stale
+            state.finished.retain(|r| r.sequence != sequence);
+            // The time isn't known until the next tick, which stamps it
+            state.tracker.track(sequence, msg, blocks.clone(), 0);
+            state.unstamped.retain(|&s| s != sequence);
+            state.unstamped.push(sequence);
+            (sequence, blocks)
+        };
+
+        for block in &blocks {
+            if let Err(e) = self.transport.send(block).await {
+                self.state.borrow_mut().tracker.cancel(sequence);
+                return Err(ClientError::Transport(e));
+            }
+        }
+
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            if let Some(i) = state.finished.iter().position(|r| r.sequence == sequence) {
+                let result = state.finished.swap_remove(i).result;
+                return Poll::Ready(result.map_err(|_| ClientError::TimedOut));
+            }
+
+            state.waiting.retain(|w| !w.will_wake(cx.waker()));
+            state.waiting.push(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl ClientState {
+    fn finish(&mut self, result: RequestResult) {
+        self.finished.push(result);
+        // Few requests are ever in flight, so everyone checks
+        for waker in self.waiting.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn queue_event(&mut self, msg: SparkToAppMsg) {
+        if !self.subscribed { return; }
+
+        if self.events.len() == MAX_QUEUED_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(msg);
+        if let Some(waker) = self.subscriber.take() {
+            waker.wake();
+        }
+    }
+}
+
+pub struct EventSubscription<'a, T> {
+    client: &'a SparkClient<T>,
+}
+
+impl<T> EventSubscription<'_, T> {
+    // Waits for the next unsolicited message
+    pub async fn next(&mut self) -> SparkToAppMsg {
+        poll_fn(|cx| {
+            let mut state = self.client.state.borrow_mut();
+            match state.events.pop_front() {
+                Some(msg) => Poll::Ready(msg),
+                None => {
+                    state.subscriber = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<T> Drop for EventSubscription<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.client.state.borrow_mut();
+        state.subscribed = false;
+        state.subscriber = None;
+        state.events.clear();
+    }
+}
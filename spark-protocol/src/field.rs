@@ -0,0 +1,252 @@
+use alloc::string::String;
+
+// One value in an unpacked Spark payload. The amp uses a MessagePack-like
+// encoding: strings are 0xA0 + len or 0xD9 len, floats 0xCA + big-endian f32,
+// booleans 0xC2 / 0xC3, lists 0x90 + count. Slots, indexes and padding are
+// plain bytes.
+pub trait SparkField: Sized {
+    fn encode(&self, w: &mut FieldWriter<'_>);
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self>;
+}
+
+// Bounds-checked cursor over an unpacked payload. Every read returns None
+// rather than panicking when the payload is short.
+pub struct FieldReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> FieldReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        FieldReader { buf, pos: 0 }
+    }
+
+    pub fn read<T: SparkField>(&mut self) -> Option<T> {
+        T::decode(self)
+    }
+
+    pub fn read_byte(&mut self) -> Option<u8> {
+        let b = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.read_bytes(N)?.try_into().ok()
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+}
+
+// Cursor for building a payload in a fixed buffer. Writes past the end are
+// dropped but still counted, so `len` is the size the payload needs, and
+// writing into an empty buffer measures a payload without storing it.
+pub struct FieldWriter<'a> {
+    buf:      &'a mut [u8],
+    len:      usize,
+    too_long: bool,
+}
+
+impl<'a> FieldWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        FieldWriter { buf, len: 0, too_long: false }
+    }
+
+    pub fn write<T: SparkField>(&mut self, value: &T) {
+        value.encode(self)
+    }
+
+    pub fn write_byte(&mut self, b: u8) {
+        if let Some(slot) = self.buf.get_mut(self.len) {
+            *slot = b;
+        }
+        self.len += 1;
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write_byte(b);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // True once more has been written than the buffer holds
+    pub fn overflowed(&self) -> bool {
+        self.len > self.buf.len()
+    }
+
+    // Marks the payload unusable, for a value its field can't hold
+    pub fn set_too_long(&mut self) {
+        self.too_long = true;
+    }
+
+    // True once a value was too long for its field, like a string over 255
+    // bytes. The payload is left without it.
+    pub fn too_long(&self) -> bool {
+        self.too_long
+    }
+}
+
+// Plain byte
+impl SparkField for u8 {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        w.write_byte(*self);
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        r.read_byte()
+    }
+}
+
+// Longest string a string field can hold
+const MAX_STRING_LEN: usize = 0xFF;
+
+// Short form below 32 bytes, long form above
+impl SparkField for String {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        let len = self.len();
+        if len > MAX_STRING_LEN {
+            w.set_too_long();
+            return;
+        }
+
+        if len < 0x20 {
+            w.write_byte(0xA0 + len as u8);
+        } else {
+            w.write_byte(0xD9);
+            w.write_byte(len as u8);
+        }
+        w.write_bytes(self.as_bytes());
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        let len = match r.read_byte()? {
+            0xD9 => r.read_byte()? as usize,
+            b @ 0xA0..=0xBF => (b - 0xA0) as usize,
+            _ => return None,
+        };
+        String::from_utf8(r.read_bytes(len)?.to_vec()).ok()
+    }
+}
+
+// A string preceded by its length, as used for effect ids and the amp's
+// identity replies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixedString(pub String);
+
+impl SparkField for PrefixedString {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        if self.0.len() > MAX_STRING_LEN {
+            w.set_too_long();
+            return;
+        }
+        w.write_byte(self.0.len() as u8);
+        self.0.encode(w);
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        r.read_byte()?;
+        Some(PrefixedString(r.read()?))
+    }
+}
+
+impl SparkField for f32 {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        w.write_byte(0xCA);
+        w.write_bytes(&self.to_be_bytes());
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        if r.read_byte()? != 0xCA { return None; }
+        Some(f32::from_be_bytes(r.read_array()?))
+    }
+}
+
+impl SparkField for bool {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        w.write_byte(if *self { 0xC3 } else { 0xC2 });
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        match r.read_byte()? {
+            0xC3 => Some(true),
+            0xC2 => Some(false),
+            _ => None,
+        }
+    }
+}
+
+// Integer, bare below 0x80, otherwise 0xCC u8, 0xCD u16 or 0xCE u32
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Int(pub u32);
+
+impl SparkField for Int {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        match self.0 {
+            v @ 0x00..=0x7F => w.write_byte(v as u8),
+            v @ 0x80..=0xFF => {
+                w.write_byte(0xCC);
+                w.write_byte(v as u8);
+            },
+            v @ 0x100..=0xFFFF => {
+                w.write_byte(0xCD);
+                w.write_bytes(&(v as u16).to_be_bytes());
+            },
+            v => {
+                w.write_byte(0xCE);
+                w.write_bytes(&v.to_be_bytes());
+            },
+        }
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        let v = match r.read_byte()? {
+            b @ 0x00..=0x7F => b as u32,
+            0xCC => r.read_byte()? as u32,
+            0xCD => u16::from_be_bytes(r.read_array()?) as u32,
+            0xCE => u32::from_be_bytes(r.read_array()?),
+            _ => return None,
+        };
+        Some(Int(v))
+    }
+}
+
+// Number of entries in the list that follows, 0x90 + n or 0xDC u16
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListLen(pub usize);
+
+impl SparkField for ListLen {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        if self.0 < 0x10 {
+            w.write_byte(0x90 + self.0 as u8);
+        } else if self.0 <= 0xFFFF {
+            w.write_byte(0xDC);
+            w.write_bytes(&(self.0 as u16).to_be_bytes());
+        } else {
+            w.set_too_long();
+        }
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        match r.read_byte()? {
+            b @ 0x90..=0x9F => Some(ListLen((b - 0x90) as usize)),
+            0xDC => Some(ListLen(u16::from_be_bytes(r.read_array()?) as usize)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,16 @@
+#![no_std]
+
+// Spark amp protocol, kept free of any hardware dependencies so it can be
+// built and tested on the host.
+
+extern crate alloc;
+
+pub mod amp;
+pub mod catalog;
+pub mod client;
+pub mod field;
+pub mod message;
+pub mod sim;
+pub mod stream;
+pub mod tracker;
+pub mod transport;
@@ -0,0 +1,1160 @@
+use alloc::vec::Vec;
+use alloc::string::String;
+use core::marker::PhantomData;
+use arrayvec::ArrayVec;
+use zerocopy::{FromBytes, IntoBytes, Unaligned, Immutable};
+use zerocopy::byteorder::{U16, U32, BigEndian};
+use crate::field::{FieldReader, FieldWriter, Int, ListLen, PrefixedString, SparkField};
+use crate::catalog::{self, EffectInfo};
+
+// The four‑byte magic value at the start of every block.
+pub const BLOCK_MAGIC: U32<BigEndian> = U32::new(0x01FE_0000);
+
+#[repr(u16)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ToSpark   = 0x53FE,
+    FromSpark = 0x41FF,
+}
+
+// 16‑byte block header (little‑endian fields):
+//  - magic (u32)
+//  - direction (u16)
+//  - size (u8)
+//  - reserved (9 bytes)
+#[repr(C)]
+#[derive(IntoBytes, FromBytes, Immutable, Unaligned, Debug)]
+pub struct BlockHeader {
+    pub magic:     U32<BigEndian>,
+    pub direction: U16<BigEndian>,
+    pub size:      u8,
+    pub _reserved: [u8; 9],
+}
+
+
+// 6‑byte SysEx chunk header (all u8, so naturally Unaligned):
+#[repr(C)]
+#[derive(IntoBytes, FromBytes, Unaligned, Immutable, Debug)]
+pub struct ChunkHeader {
+    pub start:       u8,
+    pub sysex_id:    u8,
+    pub sequence:    u8,
+    pub checksum:    u8,
+    pub command:     u8,
+    pub sub_command: u8,
+}
+
+// A message the codec can carry. The type fixes which way it travels, so the
+// same encoder and decoder serve the app, a sniffer or a fake amp.
+pub trait SparkMessage: Sized {
+    const DIRECTION: Direction;
+
+    fn opcode(&self) -> (u8, u8);
+
+    // Sequence number the message carries itself, as replies echo their
+    // request's. None has the encoder assign the next one.
+    fn wire_sequence(&self) -> Option<u8>;
+
+    fn encode_payload(&self, w: &mut FieldWriter<'_>);
+
+    // None means a known opcode whose payload didn't parse
+    fn decode_payload(sequence: u8, command: u8, sub_command: u8, raw: &[u8]) -> Option<Self>;
+
+    // Size of the unpacked payload, the scratch space encode_into needs
+    fn payload_len(&self) -> usize {
+        let mut w = FieldWriter::new(&mut []);
+        self.encode_payload(&mut w);
+        w.len()
+    }
+}
+
+// Preset slot the amp uses for the currently loaded, unsaved preset.
+pub const CURRENT_PRESET_SLOT: u8 = 0x7F;
+
+// One effect in a preset's signal chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pedal {
+    pub model:      String,
+    pub enabled:    bool,
+    pub parameters: Vec<f32>,
+}
+
+// A full preset as sent by the amp in reply to a preset request (0x03 0x01).
+// Pedals are in signal chain order: gate, comp, drive, amp, mod, delay, reverb.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    pub slot:        u8,
+    pub uuid:        String,
+    pub name:        String,
+    pub version:     String,
+    pub description: String,
+    pub icon:        String,
+    pub bpm:         f32,
+    pub pedals:      Vec<Pedal>,
+}
+
+// Tuner note names, indexed by TunerReading::note
+pub const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// Converts TunerReading::offset to cents, -50.0..=50.0 with 0.0 in tune. The
+// result is only as exact as f32 arithmetic, off by up to about 0.001 cents.
+pub fn tuner_cents(offset: f32) -> f32 {
+    (offset - 0.5) * 100.0
+}
+
+// Amp firmware version, e.g. 1.10.7.103
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub build: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AppToSparkMsg {
+    GetAmpName,
+    GetSerialNumber,
+    GetFirmwareVersion,
+    // Checksums of every hardware preset, to tell whether a cached copy is stale
+    GetPresetChecksums,
+    // The preset currently loaded, answered with a Preset
+    GetCurrentPreset,
+    // Which hardware preset button is selected
+    GetSelectedHardwarePreset,
+    SetHardwarePreset(u8),
+    // Load a preset onto the amp. Use CURRENT_PRESET_SLOT as the slot to
+    // replace the active tone without touching the hardware presets.
+    SendPreset(Preset),
+    // Switch one effect in the current preset on or off, by model id
+    ToggleEffect { effect_id: String, enabled: bool },
+    // Set one parameter of an effect in the current preset. Values are
+    // normalised to 0.0..=1.0 and clamped when encoded.
+    SetParameter { effect_id: String, param_index: u8, value: f32 },
+    // Replace one effect model in the current preset with another
+    ChangeEffect { old_id: String, new_id: String },
+    // Mute the amp and stream TunerReadings while enabled
+    SetTuner(bool),
+    // Any other request, payload unpacked and sent as-is
+    Unknown { command: u8, sub_command: u8, payload: Vec<u8> },
+}
+
+impl SparkMessage for AppToSparkMsg {
+    const DIRECTION: Direction = Direction::ToSpark;
+
+    fn opcode(&self) -> (u8, u8) {
+        match self {
+            AppToSparkMsg::GetAmpName => (0x02, 0x11),
+            AppToSparkMsg::GetSerialNumber => (0x02, 0x23),
+            AppToSparkMsg::GetFirmwareVersion => (0x02, 0x2F),
+            AppToSparkMsg::GetPresetChecksums => (0x02, 0x2A),
+            AppToSparkMsg::GetCurrentPreset => (0x02, 0x01),
+            AppToSparkMsg::GetSelectedHardwarePreset => (0x02, 0x10),
+            AppToSparkMsg::SetHardwarePreset(_) => (0x01, 0x38),
+            AppToSparkMsg::SendPreset(_) => (0x01, 0x01),
+            AppToSparkMsg::ToggleEffect { .. } => (0x01, 0x15),
+            AppToSparkMsg::SetParameter { .. } => (0x01, 0x04),
+            AppToSparkMsg::ChangeEffect { .. } => (0x01, 0x06),
+            AppToSparkMsg::SetTuner(_) => (0x01, 0x65),
+            AppToSparkMsg::Unknown { command, sub_command, .. } => (*command, *sub_command),
+        }
+    }
+
+    fn wire_sequence(&self) -> Option<u8> {
+        None
+    }
+
+    fn encode_payload(&self, w: &mut FieldWriter<'_>) {
+        match self {
+            AppToSparkMsg::GetAmpName
+            | AppToSparkMsg::GetSerialNumber
+            | AppToSparkMsg::GetFirmwareVersion
+            | AppToSparkMsg::GetPresetChecksums
+            | AppToSparkMsg::GetSelectedHardwarePreset => {
+                // no payload
+            },
+            AppToSparkMsg::GetCurrentPreset => {
+                // 0x01 0x00 selects the current preset, padded to 32 bytes
+                0x01u8.encode(w);
+                0x00u8.encode(w);
+                while w.len() < 32 {
+                    0x00u8.encode(w);
+                }
+            },
+            AppToSparkMsg::SetHardwarePreset(preset) => {
+                0x00u8.encode(w);
+                // Numbered from 1 on the amp's buttons, AmpProfile::validate rejects 0
+                preset.wrapping_sub(1).encode(w);
+            },
+            AppToSparkMsg::SendPreset(preset) => {
+                preset.encode(w);
+            },
+            AppToSparkMsg::ToggleEffect { effect_id, enabled } => {
+                PrefixedString(effect_id.clone()).encode(w);
+                enabled.encode(w);
+                0x00u8.encode(w);
+            },
+            AppToSparkMsg::SetParameter { effect_id, param_index, value } => {
+                PrefixedString(effect_id.clone()).encode(w);
+                param_index.encode(w);
+                // A NaN from a floating pot reads as 0.0
+                let value = if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) };
+                value.encode(w);
+            },
+            AppToSparkMsg::ChangeEffect { old_id, new_id } => {
+                PrefixedString(old_id.clone()).encode(w);
+                PrefixedString(new_id.clone()).encode(w);
+            },
+            AppToSparkMsg::SetTuner(enabled) => {
+                enabled.encode(w);
+            },
+            AppToSparkMsg::Unknown { payload, .. } => {
+                w.write_bytes(payload);
+            },
+        }
+    }
+
+    fn decode_payload(_sequence: u8, command: u8, sub_command: u8, raw: &[u8]) -> Option<Self> {
+        let mut r = FieldReader::new(raw);
+
+        match (command, sub_command) {
+            (0x02, 0x11) => Some(AppToSparkMsg::GetAmpName),
+            (0x02, 0x23) => Some(AppToSparkMsg::GetSerialNumber),
+            (0x02, 0x2F) => Some(AppToSparkMsg::GetFirmwareVersion),
+            (0x02, 0x2A) => Some(AppToSparkMsg::GetPresetChecksums),
+            (0x02, 0x10) => Some(AppToSparkMsg::GetSelectedHardwarePreset),
+            // Other selectors ask for a stored preset, which we don't model
+            (0x02, 0x01) if raw.starts_with(&[0x01, 0x00]) => Some(AppToSparkMsg::GetCurrentPreset),
+            (0x01, 0x38) => {
+                r.read::<u8>()?;
+                Some(AppToSparkMsg::SetHardwarePreset(r.read::<u8>()?.wrapping_add(1)))
+            }
+            (0x01, 0x01) => Some(AppToSparkMsg::SendPreset(r.read()?)),
+            (0x01, 0x15) => {
+                let msg = AppToSparkMsg::ToggleEffect {
+                    effect_id: r.read::<PrefixedString>()?.0,
+                    enabled:   r.read()?,
+                };
+                r.read::<u8>()?;
+                Some(msg)
+            }
+            (0x01, 0x04) => {
+                Some(AppToSparkMsg::SetParameter {
+                    effect_id:   r.read::<PrefixedString>()?.0,
+                    param_index: r.read()?,
+                    value:       r.read()?,
+                })
+            }
+            (0x01, 0x06) => {
+                Some(AppToSparkMsg::ChangeEffect {
+                    old_id: r.read::<PrefixedString>()?.0,
+                    new_id: r.read::<PrefixedString>()?.0,
+                })
+            }
+            (0x01, 0x65) => Some(AppToSparkMsg::SetTuner(r.read()?)),
+            (command, sub_command) => Some(AppToSparkMsg::Unknown {
+                command,
+                sub_command,
+                payload: raw.to_vec(),
+            }),
+        }
+    }
+}
+
+// Largest block the amp accepts, header included
+pub const MAX_BLOCK_SIZE: usize = 0xAD;
+const HEADER_SIZE:        usize = 0x10; // 16 byte BlockHeader
+const MAX_CHUNK_DATA:     usize = 0x80; // Unpacked payload bytes per chunk
+
+// One encoded block, sized for the largest the amp accepts
+pub type Block = ArrayVec<u8, MAX_BLOCK_SIZE>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    // The payload buffer is smaller than AppToSparkMsg::payload_len
+    BufferTooSmall { needed: usize },
+    // More than one chunk for an opcode without a multi-chunk sub-header, or
+    // more than MAX_MESSAGE_SIZE for one with
+    TooLong { command: u8, sub_command: u8 },
+    // A value too long for its field, like a string over 255 bytes
+    FieldTooLong { command: u8, sub_command: u8 },
+}
+
+// Encodes messages of type M, app to amp unless asked otherwise. An amp-side
+// encoder is SparkMsgEncoder::<SparkToAppMsg>::default().
+pub struct SparkMsgEncoder<M = AppToSparkMsg> {
+    next_sequence: u8,
+    _direction:    PhantomData<M>,
+}
+
+impl<M> Default for SparkMsgEncoder<M> {
+    fn default() -> Self {
+        SparkMsgEncoder { next_sequence: 0, _direction: PhantomData }
+    }
+}
+
+impl SparkMsgEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: SparkMessage> SparkMsgEncoder<M> {
+    // Sequence number the next encoded message will carry, unless it
+    // carries its own
+    pub fn next_sequence(&self) -> u8 {
+        self.next_sequence
+    }
+
+    // Encodes without allocating. The payload is built in `payload`, which
+    // must hold at least msg.payload_len() bytes, and blocks are packed from
+    // it one at a time as the returned iterator is advanced.
+    pub fn encode_into<'a>(&mut self, msg: &M, payload: &'a mut [u8]) -> Result<EncodedBlocks<'a>, EncodeError> {
+        let mut w = FieldWriter::new(payload);
+        msg.encode_payload(&mut w);
+        Self::check(msg, &w)?;
+        if w.overflowed() {
+            return Err(EncodeError::BufferTooSmall { needed: w.len() });
+        }
+        let len = w.len();
+
+        let payload: &'a [u8] = payload;
+        Ok(self.blocks(msg, &payload[..len]))
+    }
+
+    // Heap-allocated form of encode_into, one Vec per block
+    pub fn encode(&mut self, msg: M) -> Result<Vec<Vec<u8>>, EncodeError> {
+        let mut payload = alloc::vec![0; msg.payload_len()];
+        let mut w = FieldWriter::new(&mut payload);
+        msg.encode_payload(&mut w);
+        Self::check(&msg, &w)?;
+
+        Ok(self.blocks(&msg, &payload)
+            .map(|block| block.to_vec())
+            .collect())
+    }
+
+    // Anything longer would decode as something else, or not at all
+    fn check(msg: &M, w: &FieldWriter<'_>) -> Result<(), EncodeError> {
+        let (command, sub_command) = msg.opcode();
+        if w.too_long() {
+            return Err(EncodeError::FieldTooLong { command, sub_command });
+        }
+
+        let max = if is_multi_chunk(command, sub_command) { MAX_MESSAGE_SIZE } else { MAX_CHUNK_DATA };
+        if w.len() > max {
+            return Err(EncodeError::TooLong { command, sub_command });
+        }
+        Ok(())
+    }
+
+    fn blocks<'a>(&mut self, msg: &M, payload: &'a [u8]) -> EncodedBlocks<'a> {
+        let (command, sub_command) = msg.opcode();
+        let sequence = msg.wire_sequence().unwrap_or_else(|| {
+            let sequence = self.next_sequence;
+            self.next_sequence = sequence.wrapping_add(1);
+            sequence
+        });
+
+        EncodedBlocks {
+            direction: M::DIRECTION,
+            sequence,
+            command,
+            sub_command,
+            payload,
+            total_chunks: payload.len().div_ceil(MAX_CHUNK_DATA).max(1),
+            next_chunk:   0,
+        }
+    }
+}
+
+// Blocks of one encoded message. Every chunk of a message shares one
+// sequence number. Multi-chunk commands get a (total, index, length)
+// sub-header on each chunk before packing, and chunks are then packed into
+// as few blocks as fit.
+pub struct EncodedBlocks<'a> {
+    direction:    Direction,
+    sequence:     u8,
+    command:      u8,
+    sub_command:  u8,
+    payload:      &'a [u8],
+    total_chunks: usize,
+    next_chunk:   usize,
+}
+
+impl EncodedBlocks<'_> {
+    fn encode_7bit(input: impl IntoIterator<Item = u8>, out: &mut Block) {
+        let mut mask_index = 0;
+        for (i, b) in input.into_iter().enumerate() {
+            let bit = i % 7;
+            if bit == 0 {
+                mask_index = out.len();
+                out.push(0);
+            }
+            if b & 0x80 != 0 {
+                out[mask_index] |= 1 << bit;
+            }
+            out.push(b & 0x7F);
+        }
+    }
+
+    // Size of a chunk carrying `len` unpacked bytes, a mask byte per 7
+    fn chunk_len(len: usize) -> usize {
+        6 + len + len.div_ceil(7) + 1
+    }
+
+    fn encode_chunk(&self, index: usize, piece: &[u8], out: &mut Block) {
+        let chunk_hdr = ChunkHeader {
+            start:       0xF0,
+            sysex_id:    0x01,
+            sequence:    self.sequence,
+            checksum:    0, // filled in once packed
+            command:     self.command,
+            sub_command: self.sub_command,
+        };
+        let start = out.len();
+        out.extend(chunk_hdr.as_bytes().iter().copied());
+
+        let sub_header: &[u8] = if is_multi_chunk(self.command, self.sub_command) {
+            &[self.total_chunks as u8, index as u8, piece.len() as u8]
+        } else {
+            &[]
+        };
+        Self::encode_7bit(sub_header.iter().chain(piece).copied(), out);
+
+        out[start + 3] = out[start + 6..].iter().fold(0u8, |acc, &b| acc ^ b);
+        out.push(0xF7);
+    }
+}
+
+impl Iterator for EncodedBlocks<'_> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Block> {
+        if self.next_chunk >= self.total_chunks { return None; }
+
+        let block_hdr = BlockHeader {
+            magic:     BLOCK_MAGIC,
+            direction: U16::new(self.direction as u16),
+            size:      0, // filled in once the chunks are in
+            _reserved: [0; 9],
+        };
+        let mut block = Block::new();
+        block.extend(block_hdr.as_bytes().iter().copied());
+
+        let sub_header_len = if is_multi_chunk(self.command, self.sub_command) { 3 } else { 0 };
+        while self.next_chunk < self.total_chunks {
+            let index = self.next_chunk;
+            let start = index * MAX_CHUNK_DATA;
+            let piece = &self.payload[start..(start + MAX_CHUNK_DATA).min(self.payload.len())];
+
+            let chunk_len = Self::chunk_len(sub_header_len + piece.len());
+            if block.len() > HEADER_SIZE && block.len() + chunk_len > MAX_BLOCK_SIZE { break; }
+
+            self.encode_chunk(index, piece, &mut block);
+            self.next_chunk += 1;
+        }
+
+        block[6] = block.len() as u8;
+        Some(block)
+    }
+}
+
+impl Pedal {
+    // Catalog entry for this pedal's model, if it's one we know
+    pub fn info(&self) -> Option<&'static EffectInfo> {
+        catalog::find(&self.model)
+    }
+}
+
+// Parameters are written as index, 0x91, value
+impl SparkField for Pedal {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        self.model.encode(w);
+        self.enabled.encode(w);
+        // Parameters are indexed by a single byte
+        if self.parameters.len() > 0x100 {
+            w.set_too_long();
+            return;
+        }
+
+        ListLen(self.parameters.len()).encode(w);
+        for (index, value) in self.parameters.iter().enumerate() {
+            (index as u8).encode(w);
+            0x91u8.encode(w);
+            value.encode(w);
+        }
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        let model   = r.read()?;
+        let enabled = r.read()?;
+
+        // Counts come off the wire, so nothing is reserved up front
+        let ListLen(num_params) = r.read()?;
+        let mut parameters = Vec::new();
+        for _ in 0..num_params {
+            r.read::<u8>()?; // parameter index
+            r.read::<u8>()?; // 0x91
+            parameters.push(r.read()?);
+        }
+
+        Some(Pedal { model, enabled, parameters })
+    }
+}
+
+impl SparkField for Preset {
+    fn encode(&self, w: &mut FieldWriter<'_>) {
+        0x00u8.encode(w);
+        self.slot.encode(w);
+        self.uuid.encode(w);
+        self.name.encode(w);
+        self.version.encode(w);
+        self.description.encode(w);
+        self.icon.encode(w);
+        self.bpm.encode(w);
+
+        ListLen(self.pedals.len()).encode(w);
+        for pedal in &self.pedals {
+            pedal.encode(w);
+        }
+
+        // Trailing checksum byte, the amp recomputes it on store
+        0x00u8.encode(w);
+    }
+
+    fn decode(r: &mut FieldReader<'_>) -> Option<Self> {
+        r.read::<u8>()?;
+        let slot        = r.read()?;
+        let uuid        = r.read()?;
+        let name        = r.read()?;
+        let version     = r.read()?;
+        let description = r.read()?;
+        let icon        = r.read()?;
+        let bpm         = r.read()?;
+
+        let ListLen(num_pedals) = r.read()?;
+        let mut pedals = Vec::new();
+        for _ in 0..num_pedals {
+            pedals.push(r.read()?);
+        }
+
+        Some(Preset { slot, uuid, name, version, description, icon, bpm, pedals })
+    }
+}
+
+// Accumulates raw notification bytes and splits them into whole blocks.
+// A block may arrive across several notifications, and one notification may
+// carry several blocks; anything that isn't a block is skipped until the
+// next BLOCK_MAGIC.
+#[derive(Default)]
+pub struct SparkBlockFramer {
+    buf:     Vec<u8>,
+    skipped: usize,
+}
+
+impl SparkBlockFramer {
+    pub fn new() -> Self {
+        SparkBlockFramer { buf: Vec::new(), skipped: 0 }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    // Bytes thrown away so far while looking for a block
+    pub fn skipped_bytes(&self) -> usize {
+        self.skipped
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.buf.drain(..len);
+        self.skipped = self.skipped.wrapping_add(len);
+    }
+
+    fn find_magic(buf: &[u8]) -> Option<usize> {
+        buf.windows(4).position(|w| w == BLOCK_MAGIC.as_bytes())
+    }
+
+    // Returns the next complete block, or None until more bytes are pushed.
+    pub fn next_block(&mut self) -> Option<Vec<u8>> {
+        loop {
+            match Self::find_magic(&self.buf) {
+                Some(start) => self.skip(start),
+                None => {
+                    // Keep a possible partial magic at the tail
+                    let keep = self.buf.len().min(3);
+                    self.skip(self.buf.len() - keep);
+                    return None;
+                }
+            }
+
+            let Ok((hdr, _)) = BlockHeader::read_from_prefix(&self.buf) else { return None; };
+
+            let direction = hdr.direction.get();
+            let size = hdr.size as usize;
+            if size < 16
+                || (direction != Direction::ToSpark as u16 && direction != Direction::FromSpark as u16)
+            {
+                // Not really a block header, look for the next one
+                self.skip(1);
+                continue;
+            }
+
+            // Bytes >= 0x80 other than 0xF0/0xF7 never appear in a block body, so
+            // a magic inside this block means it was cut short. Resync there.
+            let end = size.min(self.buf.len());
+            if let Some(next) = Self::find_magic(&self.buf[4..end]) {
+                self.skip(4 + next);
+                continue;
+            }
+
+            if self.buf.len() < size { return None; }
+
+            return Some(self.buf.drain(..size).collect());
+        }
+    }
+}
+
+// Commands whose payload is split into chunks, each starting with a
+// (total chunks, chunk index, chunk length) sub-header before 7-bit packing.
+pub fn is_multi_chunk(command: u8, sub_command: u8) -> bool {
+    matches!((command, sub_command), (0x01, 0x01) | (0x03, 0x01))
+}
+
+// Largest multi-chunk message the decoder reassembles, presets come in
+// well under this
+pub const MAX_MESSAGE_SIZE: usize = 0x800;
+// Multi-chunk messages reassembled at once, one per opcode
+const MAX_PENDING:          usize = 2;
+
+// A complete message with its payload unpacked but not yet parsed. The
+// payload borrows from the block it arrived in, or from the decoder for
+// reassembled multi-chunk messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawMessage<'a> {
+    pub sequence:    u8,
+    pub command:     u8,
+    pub sub_command: u8,
+    pub payload:     &'a [u8],
+}
+
+// A multi-chunk message that is still being reassembled.
+struct PendingMessage {
+    sequence:     u8,
+    command:      u8,
+    sub_command:  u8,
+    total_chunks: u8,
+    next_chunk:   u8,
+    data:         ArrayVec<u8, MAX_MESSAGE_SIZE>,
+}
+
+// Where decode_chunk left a completed message's payload
+enum Payload {
+    // Unpacked in place, this many bytes at the start of the chunk data
+    InChunk(usize),
+    // Reassembled in this pending slot
+    Pending(usize),
+}
+
+// Running counts of what the decoder has seen, to tell radio corruption
+// (checksum errors) apart from protocol bugs (everything else).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecoderDiagnostics {
+    // Complete messages, including any whose payload then fails to parse
+    pub messages:        u32,
+    pub checksum_errors: u32,
+    pub other_errors:    u32,
+}
+
+// Parses incoming blocks, buffering multi-chunk messages until every chunk
+// has arrived. Decodes amp to app messages unless asked otherwise, a decoder
+// for the app's side is SparkMsgDecoder::<AppToSparkMsg>::default().
+pub struct SparkMsgDecoder<M = SparkToAppMsg> {
+    pending:     ArrayVec<PendingMessage, MAX_PENDING>,
+    diagnostics: DecoderDiagnostics,
+    _direction:  PhantomData<M>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SparkToAppMsg {
+    AmpName { sequence: u8, name: String },
+    Preset { sequence: u8, preset: Preset },
+    SerialNumber { sequence: u8, serial: String },
+    FirmwareVersion { sequence: u8, version: FirmwareVersion },
+    // Indexed by hardware preset slot, starting at 0
+    PresetChecksums { sequence: u8, checksums: Vec<u8> },
+    // Unsolicited events sent when the amp's own controls are used
+    ParameterChanged { sequence: u8, effect_id: String, param_index: u8, value: f32 },
+    HardwarePresetChanged { sequence: u8, preset: u8 },
+    // Reply to GetSelectedHardwarePreset, numbered from 1 like SetHardwarePreset
+    SelectedHardwarePreset { sequence: u8, preset: u8 },
+    EffectChanged { sequence: u8, old_id: String, new_id: String },
+    EffectToggled { sequence: u8, effect_id: String, enabled: bool },
+    // Streamed while the tuner is on. note indexes NOTE_NAMES, offset is as
+    // sent, 0.0..=1.0 with 0.5 in tune. tuner_cents converts it.
+    TunerReading { sequence: u8, note: u8, offset: f32 },
+    // Acknowledgement of a command, by the command's sub-command
+    Ack { sequence: u8, sub_command: u8 },
+    // Well-formed message with an opcode we don't decode yet, payload unpacked
+    Unknown { sequence: u8, command: u8, sub_command: u8, payload: Vec<u8> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    // Shorter than a block header plus one chunk, or than its own size field
+    TruncatedBlock,
+    BadMagic,
+    WrongDirection,
+    // Chunk without its 0xF0 0x01 start or its 0xF7 trailer
+    BadChunk,
+    // Chunk data doesn't XOR to the checksum in its header
+    BadChecksum { command: u8, sub_command: u8 },
+    // Multi-chunk sub-header missing or inconsistent with its data
+    BadSubHeader { command: u8, sub_command: u8 },
+    // A chunk that doesn't continue the message being reassembled
+    OutOfSequence { command: u8, sub_command: u8 },
+    // Complete message whose payload doesn't parse
+    CorruptPayload { command: u8, sub_command: u8 },
+    // Multi-chunk message longer than MAX_MESSAGE_SIZE
+    TooLong { command: u8, sub_command: u8 },
+}
+
+impl SparkMessage for SparkToAppMsg {
+    const DIRECTION: Direction = Direction::FromSpark;
+
+    fn opcode(&self) -> (u8, u8) {
+        match self {
+            SparkToAppMsg::AmpName { .. } => (0x03, 0x11),
+            SparkToAppMsg::Preset { .. } => (0x03, 0x01),
+            SparkToAppMsg::SerialNumber { .. } => (0x03, 0x23),
+            SparkToAppMsg::FirmwareVersion { .. } => (0x03, 0x2F),
+            SparkToAppMsg::PresetChecksums { .. } => (0x03, 0x2A),
+            SparkToAppMsg::ParameterChanged { .. } => (0x03, 0x37),
+            SparkToAppMsg::HardwarePresetChanged { .. } => (0x03, 0x38),
+            SparkToAppMsg::SelectedHardwarePreset { .. } => (0x03, 0x10),
+            SparkToAppMsg::EffectChanged { .. } => (0x03, 0x06),
+            SparkToAppMsg::EffectToggled { .. } => (0x03, 0x15),
+            SparkToAppMsg::TunerReading { .. } => (0x03, 0x64),
+            SparkToAppMsg::Ack { sub_command, .. } => (0x04, *sub_command),
+            SparkToAppMsg::Unknown { command, sub_command, .. } => (*command, *sub_command),
+        }
+    }
+
+    fn wire_sequence(&self) -> Option<u8> {
+        Some(self.sequence())
+    }
+
+    fn encode_payload(&self, w: &mut FieldWriter<'_>) {
+        match self {
+            SparkToAppMsg::AmpName { name, .. } => {
+                PrefixedString(name.clone()).encode(w);
+            },
+            SparkToAppMsg::Preset { preset, .. } => {
+                preset.encode(w);
+            },
+            SparkToAppMsg::SerialNumber { serial, .. } => {
+                PrefixedString(serial.clone()).encode(w);
+            },
+            SparkToAppMsg::FirmwareVersion { version, .. } => {
+                let FirmwareVersion { major, minor, patch, build } = *version;
+                Int(u32::from_be_bytes([major, minor, patch, build])).encode(w);
+            },
+            SparkToAppMsg::PresetChecksums { checksums, .. } => {
+                ListLen(checksums.len()).encode(w);
+                for &checksum in checksums {
+                    Int(checksum as u32).encode(w);
+                }
+            },
+            SparkToAppMsg::Ack { .. } => {
+                // no payload
+            },
+            SparkToAppMsg::ParameterChanged { effect_id, param_index, value, .. } => {
+                PrefixedString(effect_id.clone()).encode(w);
+                param_index.encode(w);
+                value.encode(w);
+            },
+            SparkToAppMsg::HardwarePresetChanged { preset, .. }
+            | SparkToAppMsg::SelectedHardwarePreset { preset, .. } => {
+                0x00u8.encode(w);
+                preset.wrapping_sub(1).encode(w);
+            },
+            SparkToAppMsg::EffectChanged { old_id, new_id, .. } => {
+                PrefixedString(old_id.clone()).encode(w);
+                PrefixedString(new_id.clone()).encode(w);
+            },
+            SparkToAppMsg::EffectToggled { effect_id, enabled, .. } => {
+                PrefixedString(effect_id.clone()).encode(w);
+                enabled.encode(w);
+            },
+            SparkToAppMsg::TunerReading { note, offset, .. } => {
+                Int(*note as u32).encode(w);
+                offset.encode(w);
+            },
+            SparkToAppMsg::Unknown { payload, .. } => {
+                w.write_bytes(payload);
+            },
+        }
+    }
+
+    fn decode_payload(sequence: u8, command: u8, subcommand: u8, raw: &[u8]) -> Option<Self> {
+        let mut r = FieldReader::new(raw);
+
+        match (command, subcommand) {
+            // GetAmpName
+            (0x03, 0x11) => {
+                let PrefixedString(name) = r.read()?;
+                Some(SparkToAppMsg::AmpName {
+                    sequence,
+                    name,
+                })
+            }
+            // Preset
+            (0x03, 0x01) => {
+                Some(SparkToAppMsg::Preset {
+                    sequence,
+                    preset: r.read()?,
+                })
+            }
+            // GetSerialNumber
+            (0x03, 0x23) => {
+                let PrefixedString(serial) = r.read()?;
+                Some(SparkToAppMsg::SerialNumber {
+                    sequence,
+                    serial,
+                })
+            }
+            // GetFirmwareVersion, one byte per component packed in an integer
+            (0x03, 0x2F) => {
+                let Int(packed) = r.read()?;
+                let [major, minor, patch, build] = packed.to_be_bytes();
+                Some(SparkToAppMsg::FirmwareVersion {
+                    sequence,
+                    version: FirmwareVersion { major, minor, patch, build },
+                })
+            }
+            // GetPresetChecksums
+            (0x03, 0x2A) => {
+                let ListLen(count) = r.read()?;
+                let checksums = (0..count)
+                    .map(|_| r.read::<Int>().and_then(|Int(v)| u8::try_from(v).ok()))
+                    .collect::<Option<Vec<u8>>>()?;
+                Some(SparkToAppMsg::PresetChecksums {
+                    sequence,
+                    checksums,
+                })
+            }
+            // Knob turned on the amp
+            (0x03, 0x37) => {
+                Some(SparkToAppMsg::ParameterChanged {
+                    sequence,
+                    effect_id:   r.read::<PrefixedString>()?.0,
+                    param_index: r.read()?,
+                    value:       r.read()?,
+                })
+            }
+            // Preset button pressed on the amp, numbered from 1 like SetHardwarePreset
+            (0x03, 0x38) => {
+                r.read::<u8>()?;
+                Some(SparkToAppMsg::HardwarePresetChanged {
+                    sequence,
+                    preset: r.read::<u8>()?.wrapping_add(1),
+                })
+            }
+            // GetSelectedHardwarePreset
+            (0x03, 0x10) => {
+                r.read::<u8>()?;
+                Some(SparkToAppMsg::SelectedHardwarePreset {
+                    sequence,
+                    preset: r.read::<u8>()?.wrapping_add(1),
+                })
+            }
+            // Effect model swapped on the amp
+            (0x03, 0x06) => {
+                Some(SparkToAppMsg::EffectChanged {
+                    sequence,
+                    old_id: r.read::<PrefixedString>()?.0,
+                    new_id: r.read::<PrefixedString>()?.0,
+                })
+            }
+            // Effect switched on or off on the amp
+            (0x03, 0x15) => {
+                Some(SparkToAppMsg::EffectToggled {
+                    sequence,
+                    effect_id: r.read::<PrefixedString>()?.0,
+                    enabled:   r.read()?,
+                })
+            }
+            // Tuner, the offset is sent as 0.0..=1.0 with 0.5 in tune
+            (0x03, 0x64) => {
+                let Int(note) = r.read()?;
+                Some(SparkToAppMsg::TunerReading {
+                    sequence,
+                    note:   u8::try_from(note).ok()?,
+                    offset: r.read()?,
+                })
+            }
+            (0x04, sub_command) => Some(SparkToAppMsg::Ack { sequence, sub_command }),
+            (command, sub_command) => Some(SparkToAppMsg::Unknown {
+                sequence,
+                command,
+                sub_command,
+                payload: raw.to_vec(),
+            }),
+        }
+    }
+}
+
+impl SparkToAppMsg {
+    pub fn sequence(&self) -> u8 {
+        match self {
+            SparkToAppMsg::AmpName { sequence, .. }
+            | SparkToAppMsg::Preset { sequence, .. }
+            | SparkToAppMsg::SerialNumber { sequence, .. }
+            | SparkToAppMsg::FirmwareVersion { sequence, .. }
+            | SparkToAppMsg::PresetChecksums { sequence, .. }
+            | SparkToAppMsg::ParameterChanged { sequence, .. }
+            | SparkToAppMsg::HardwarePresetChanged { sequence, .. }
+            | SparkToAppMsg::SelectedHardwarePreset { sequence, .. }
+            | SparkToAppMsg::EffectChanged { sequence, .. }
+            | SparkToAppMsg::EffectToggled { sequence, .. }
+            | SparkToAppMsg::TunerReading { sequence, .. }
+            | SparkToAppMsg::Ack { sequence, .. }
+            | SparkToAppMsg::Unknown { sequence, .. } => *sequence,
+        }
+    }
+}
+
+impl<M> Default for SparkMsgDecoder<M> {
+    fn default() -> Self {
+        SparkMsgDecoder {
+            pending:     ArrayVec::new(),
+            diagnostics: DecoderDiagnostics::default(),
+            _direction:  PhantomData,
+        }
+    }
+}
+
+impl SparkMsgDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: SparkMessage> SparkMsgDecoder<M> {
+    pub fn diagnostics(&self) -> DecoderDiagnostics {
+        self.diagnostics
+    }
+
+    // Unpacks 7-bit data over itself, the output never overtakes the input.
+    // Returns the unpacked length.
+    fn decode_7bit(buf: &mut [u8]) -> usize {
+        let mut out = 0;
+        let mut i = 0;
+        while i < buf.len() {
+            let mask = buf[i];
+            i += 1;
+            // up to 7 bytes follow
+            for bit in 0..7 {
+                if i >= buf.len() { break; }
+                let b = buf[i];
+                buf[out] = if (mask >> bit) & 1 == 1 {
+                    b | 0x80
+                } else {
+                    b
+                };
+                out += 1;
+                i += 1;
+            }
+        }
+        out
+    }
+
+    // Checks the block header and returns the chunks that follow it.
+    fn block_body(buf: &mut [u8]) -> Result<&mut [u8], DecodeError> {
+        // Must be at least header + chunk header + trailer
+        if buf.len() < 16 + 6 + 1 { return Err(DecodeError::TruncatedBlock); }
+
+        let (hdr, _)      = BlockHeader::read_from_prefix(buf).map_err(|_| DecodeError::TruncatedBlock)?;
+        if hdr.magic     != BLOCK_MAGIC { return Err(DecodeError::BadMagic); }
+        if hdr.direction != M::DIRECTION as u16 { return Err(DecodeError::WrongDirection); }
+
+        let size = hdr.size as usize;
+        if size < 16 + 6 + 1 || size > buf.len() { return Err(DecodeError::TruncatedBlock); }
+
+        Ok(&mut buf[16..size])
+    }
+
+    // Feeds one chunk into the reassembly state, unpacking its data in place.
+    // Returns where the message's payload is once its final chunk has been
+    // seen, None while more chunks are expected.
+    fn decode_chunk(&mut self, hdr: &ChunkHeader, data: &mut [u8]) -> Result<Option<Payload>, DecodeError> {
+        let (command, sub_command) = (hdr.command, hdr.sub_command);
+
+        // Same rule as the encoder, XOR over the packed data
+        let checksum = data.iter().fold(0u8, |acc, &b| acc ^ b);
+        if checksum != hdr.checksum {
+            self.pending.retain(|p| p.command != command || p.sub_command != sub_command);
+            return Err(DecodeError::BadChecksum { command, sub_command });
+        }
+
+        let len = Self::decode_7bit(data);
+
+        if !is_multi_chunk(command, sub_command) {
+            return Ok(Some(Payload::InChunk(len)));
+        }
+
+        let slot = self.pending.iter().position(|p| {
+            p.command == command && p.sub_command == sub_command
+        });
+
+        // A chunk that doesn't fit the pending message means we missed
+        // something; drop what we have rather than emit a corrupt message.
+        let (total_chunks, index, data) = match &data[..len] {
+            &[total_chunks, index, len, ref data @ ..]
+                if total_chunks > 0 && index < total_chunks && data.len() >= len as usize =>
+            {
+                (total_chunks, index, &data[..len as usize])
+            }
+            _ => {
+                if let Some(slot) = slot { self.pending.swap_remove(slot); }
+                return Err(DecodeError::BadSubHeader { command, sub_command });
+            }
+        };
+
+        let slot = if index == 0 {
+            if let Some(slot) = slot { self.pending.swap_remove(slot); }
+            // Oldest message gives way if every slot is in use
+            if self.pending.is_full() { self.pending.remove(0); }
+            self.pending.push(PendingMessage {
+                sequence:     hdr.sequence,
+                command,
+                sub_command,
+                total_chunks,
+                next_chunk:   0,
+                data:         ArrayVec::new(),
+            });
+            self.pending.len() - 1
+        } else {
+            slot.ok_or(DecodeError::OutOfSequence { command, sub_command })?
+        };
+
+        let pending = &mut self.pending[slot];
+        if pending.sequence != hdr.sequence
+            || pending.total_chunks != total_chunks
+            || pending.next_chunk != index
+        {
+            self.pending.swap_remove(slot);
+            return Err(DecodeError::OutOfSequence { command, sub_command });
+        }
+
+        if pending.data.try_extend_from_slice(data).is_err() {
+            self.pending.swap_remove(slot);
+            return Err(DecodeError::TooLong { command, sub_command });
+        }
+        pending.next_chunk += 1;
+        if pending.next_chunk < pending.total_chunks { return Ok(None); }
+
+        Ok(Some(Payload::Pending(slot)))
+    }
+
+    fn parse_raw(raw: &RawMessage) -> Result<M, DecodeError> {
+        M::decode_payload(raw.sequence, raw.command, raw.sub_command, raw.payload)
+            .ok_or(DecodeError::CorruptPayload { command: raw.command, sub_command: raw.sub_command })
+    }
+
+    fn count_error(&mut self, error: DecodeError) {
+        let count = match error {
+            DecodeError::BadChecksum { .. } => &mut self.diagnostics.checksum_errors,
+            _ => &mut self.diagnostics.other_errors,
+        };
+        *count = count.wrapping_add(1);
+    }
+
+    // Decodes every chunk in a block without allocating, unpacking chunk
+    // data in place. `on_message` is called with each message the block
+    // completes and each error along the way.
+    pub fn decode_in_place(&mut self, block: &mut [u8], mut on_message: impl FnMut(Result<RawMessage<'_>, DecodeError>)) {
+        let mut body = match Self::block_body(block) {
+            Ok(body) => body,
+            Err(e) => {
+                self.count_error(e);
+                return on_message(Err(e));
+            }
+        };
+
+        // Each chunk is terminated by 0xF7, which can never appear in 7-bit
+        // packed data
+        while !body.is_empty() {
+            let Some((hdr, end)) = ChunkHeader::read_from_prefix(body).ok()
+                .filter(|(hdr, _)| hdr.start == 0xF0 && hdr.sysex_id == 0x01)
+                .and_then(|(hdr, rest)| Some((hdr, rest.iter().position(|&b| b == 0xF7)?)))
+            else {
+                self.count_error(DecodeError::BadChunk);
+                return on_message(Err(DecodeError::BadChunk));
+            };
+
+            let (chunk, rest) = core::mem::take(&mut body).split_at_mut(6 + end + 1);
+            body = rest;
+            let data = &mut chunk[6..6 + end];
+
+            let payload = match self.decode_chunk(&hdr, data) {
+                Ok(Some(payload)) => payload,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.count_error(e);
+                    on_message(Err(e));
+                    continue;
+                }
+            };
+
+            self.diagnostics.messages = self.diagnostics.messages.wrapping_add(1);
+            let raw = |payload| RawMessage {
+                sequence:    hdr.sequence,
+                command:     hdr.command,
+                sub_command: hdr.sub_command,
+                payload,
+            };
+            match payload {
+                Payload::InChunk(len) => on_message(Ok(raw(&data[..len]))),
+                Payload::Pending(slot) => {
+                    on_message(Ok(raw(&self.pending[slot].data)));
+                    self.pending.swap_remove(slot);
+                },
+            }
+        }
+    }
+
+    // Parses a message from decode_in_place into its owned form
+    pub fn parse(&mut self, raw: &RawMessage) -> Result<M, DecodeError> {
+        let result = Self::parse_raw(raw);
+        if let Err(e) = result { self.count_error(e); }
+        result
+    }
+
+    // Heap-allocated form of decode_in_place, returning the parsed messages
+    // the block completed and any errors along the way.
+    pub fn decode(&mut self, block: &[u8]) -> Vec<Result<M, DecodeError>> {
+        self.decode_with_sequence(block)
+            .into_iter()
+            .map(|result| result.map(|(_, msg)| msg))
+            .collect()
+    }
+
+    // Like decode, with each message's sequence number alongside it. Only
+    // needed for AppToSparkMsg, which doesn't carry its own.
+    pub fn decode_with_sequence(&mut self, block: &[u8]) -> Vec<Result<(u8, M), DecodeError>> {
+        let mut block = block.to_vec();
+        let mut results = Vec::new();
+        self.decode_in_place(&mut block, |result| {
+            results.push(result.and_then(|raw| Ok((raw.sequence, Self::parse_raw(&raw)?))));
+        });
+
+        // Counted here rather than by decode_in_place, which doesn't parse
+        for result in &results {
+            if let Err(e @ DecodeError::CorruptPayload { .. }) = result { self.count_error(*e); }
+        }
+
+        results
+    }
+}
@@ -0,0 +1,307 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use crate::amp::{AmpModel, AmpProfile};
+use crate::field::FieldWriter;
+use crate::message::{AppToSparkMsg, FirmwareVersion, Preset, SparkMessage, SparkMsgEncoder, SparkToAppMsg, CURRENT_PRESET_SLOT};
+use crate::stream::SparkStreamDecoder;
+use crate::transport::{LinkState, SparkTransport};
+
+// What the amp reports about itself. The model, and so which commands it
+// accepts, comes from the name the same way it does for a real amp.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimIdentity {
+    pub name:     String,
+    pub serial:   String,
+    pub firmware: FirmwareVersion,
+}
+
+impl SimIdentity {
+    pub fn spark_40() -> Self {
+        SimIdentity {
+            name:     "Spark 40 Audio".into(),
+            serial:   "S40D000000001".into(),
+            firmware: FirmwareVersion { major: 1, minor: 10, patch: 7, build: 103 },
+        }
+    }
+}
+
+// An unsolicited message, as if the amp's own controls were used, sent once
+// the amp has handled `after_requests` requests in total. Events already due
+// when a request arrives go out ahead of its reply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptedEvent {
+    pub after_requests: usize,
+    pub event:          SparkToAppMsg,
+}
+
+struct SimState {
+    // Numbered from 1 like SetHardwarePreset
+    hardware_preset: u8,
+    // Indexed by hardware preset slot, starting at 0
+    presets:         Vec<Preset>,
+    current:         Preset,
+    tuner:           bool,
+    handled:         usize,
+    script:          VecDeque<ScriptedEvent>,
+    stream:          SparkStreamDecoder<AppToSparkMsg>,
+    encoder:         SparkMsgEncoder<SparkToAppMsg>,
+}
+
+// The amp end of a link, for exercising the app side without hardware. It
+// answers queries from its identity and presets, applies and acknowledges
+// commands, and plays back scripted events.
+//
+// Requests a real amp would reject, like a hardware preset it doesn't have,
+// get no reply at all, so the app's timeouts and retries can be tested too.
+pub struct SimulatedAmp<T> {
+    transport: T,
+    identity:  SimIdentity,
+    profile:   AmpProfile,
+    state:     RefCell<SimState>,
+}
+
+impl<T: SparkTransport> SimulatedAmp<T> {
+    // Starts on hardware preset 1, with `presets` stored from slot 0 up.
+    // Slots left out are empty and selecting them keeps the current preset.
+    pub fn new(transport: T, identity: SimIdentity, presets: Vec<Preset>) -> Self {
+        let profile = AmpModel::from_name(&identity.name).profile();
+        let current = presets.first().cloned().unwrap_or_else(empty_preset);
+        SimulatedAmp {
+            transport,
+            identity,
+            profile,
+            state: RefCell::new(SimState {
+                hardware_preset: 1,
+                presets,
+                current,
+                tuner:   false,
+                handled: 0,
+                script:  VecDeque::new(),
+                stream:  SparkStreamDecoder::default(),
+                encoder: SparkMsgEncoder::default(),
+            }),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    // Queues an event. Events are sent in the order they were scripted.
+    pub fn script(&self, event: ScriptedEvent) {
+        self.state.borrow_mut().script.push_back(event);
+    }
+
+    pub fn hardware_preset(&self) -> u8 {
+        self.state.borrow().hardware_preset
+    }
+
+    pub fn current_preset(&self) -> Preset {
+        self.state.borrow().current.clone()
+    }
+
+    pub fn stored_preset(&self, slot: u8) -> Option<Preset> {
+        self.state.borrow().presets.get(slot as usize).cloned()
+    }
+
+    pub fn tuner_enabled(&self) -> bool {
+        self.state.borrow().tuner
+    }
+
+    // Requests decoded so far, answered or not
+    pub fn requests_handled(&self) -> usize {
+        self.state.borrow().handled
+    }
+
+    // Answers requests until the link drops, which ends the run cleanly.
+    // Blocks that fail to decode are dropped, as a real amp does.
+    pub async fn run(&self) -> Result<(), T::Error> {
+        let mut buf = [0u8; 256];
+        loop {
+            let len = match self.transport.receive(&mut buf).await {
+                Ok(len) => len,
+                Err(_) if self.transport.link_state() == LinkState::Disconnected => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            self.state.borrow_mut().stream.push(&buf[..len]);
+
+            loop {
+                // Not held across the sends below
+                let next = self.state.borrow_mut().stream.next_with_sequence();
+                let Some(result) = next else { break };
+                let Ok((sequence, request)) = result else { continue };
+
+                while let Some(event) = self.next_due_event() {
+                    self.send_event(event).await?;
+                }
+                let reply = self.handle(sequence, &request);
+                if let Some(reply) = reply {
+                    self.send_raw(reply).await?;
+                }
+                while let Some(event) = self.next_due_event() {
+                    self.send_event(event).await?;
+                }
+            }
+        }
+    }
+
+    // Sends an unsolicited event now, applying it to the amp's state first
+    pub async fn send_event(&self, event: SparkToAppMsg) -> Result<(), T::Error> {
+        self.apply_event(&event);
+        self.send_raw(event).await
+    }
+
+    async fn send_raw(&self, msg: SparkToAppMsg) -> Result<(), T::Error> {
+        // Nothing a real amp could send, so it's dropped
+        let Ok(blocks) = self.state.borrow_mut().encoder.encode(msg) else { return Ok(()) };
+        for block in blocks {
+            self.transport.send(&block).await?;
+        }
+        Ok(())
+    }
+
+    fn next_due_event(&self) -> Option<SparkToAppMsg> {
+        let mut state = self.state.borrow_mut();
+        if state.script.front()?.after_requests > state.handled { return None; }
+        state.script.pop_front().map(|scripted| scripted.event)
+    }
+
+    fn apply_event(&self, event: &SparkToAppMsg) {
+        let mut state = self.state.borrow_mut();
+        match event {
+            SparkToAppMsg::ParameterChanged { effect_id, param_index, value, .. } => {
+                set_parameter(&mut state.current, effect_id, *param_index, *value);
+            }
+            SparkToAppMsg::HardwarePresetChanged { preset, .. } => {
+                state.select(*preset);
+            }
+            SparkToAppMsg::EffectToggled { effect_id, enabled, .. } => {
+                if let Some(pedal) = state.current.pedals.iter_mut().find(|p| p.model == *effect_id) {
+                    pedal.enabled = *enabled;
+                }
+            }
+            SparkToAppMsg::EffectChanged { old_id, new_id, .. } => {
+                change_effect(&mut state.current, old_id, new_id);
+            }
+            _ => {}
+        }
+    }
+
+    // Applies one request, returning the reply if it gets one
+    fn handle(&self, sequence: u8, request: &AppToSparkMsg) -> Option<SparkToAppMsg> {
+        let mut state = self.state.borrow_mut();
+        state.handled += 1;
+
+        if self.profile.validate(request).is_err() { return None; }
+
+        let reply = match request {
+            AppToSparkMsg::GetAmpName => {
+                SparkToAppMsg::AmpName { sequence, name: self.identity.name.clone() }
+            }
+            AppToSparkMsg::GetSerialNumber => {
+                SparkToAppMsg::SerialNumber { sequence, serial: self.identity.serial.clone() }
+            }
+            AppToSparkMsg::GetFirmwareVersion => {
+                SparkToAppMsg::FirmwareVersion { sequence, version: self.identity.firmware }
+            }
+            AppToSparkMsg::GetPresetChecksums => {
+                let mut checksums = vec![0; self.profile.hardware_presets as usize];
+                for (checksum, preset) in checksums.iter_mut().zip(&state.presets) {
+                    *checksum = preset_checksum(preset);
+                }
+                SparkToAppMsg::PresetChecksums { sequence, checksums }
+            }
+            AppToSparkMsg::GetCurrentPreset => {
+                SparkToAppMsg::Preset { sequence, preset: state.current.clone() }
+            }
+            AppToSparkMsg::GetSelectedHardwarePreset => {
+                SparkToAppMsg::SelectedHardwarePreset { sequence, preset: state.hardware_preset }
+            }
+            AppToSparkMsg::SetHardwarePreset(preset) => {
+                state.select(*preset);
+                SparkToAppMsg::Ack { sequence, sub_command: 0x38 }
+            }
+            AppToSparkMsg::SendPreset(preset) => {
+                if preset.slot == CURRENT_PRESET_SLOT {
+                    state.current = preset.clone();
+                } else {
+                    let slot = preset.slot as usize;
+                    if state.presets.len() <= slot {
+                        state.presets.resize_with(slot + 1, empty_preset);
+                    }
+                    state.presets[slot] = preset.clone();
+                }
+                SparkToAppMsg::Ack { sequence, sub_command: 0x01 }
+            }
+            AppToSparkMsg::ToggleEffect { effect_id, enabled } => {
+                if let Some(pedal) = state.current.pedals.iter_mut().find(|p| p.model == *effect_id) {
+                    pedal.enabled = *enabled;
+                }
+                SparkToAppMsg::Ack { sequence, sub_command: 0x15 }
+            }
+            AppToSparkMsg::SetParameter { effect_id, param_index, value } => {
+                set_parameter(&mut state.current, effect_id, *param_index, *value);
+                SparkToAppMsg::Ack { sequence, sub_command: 0x04 }
+            }
+            AppToSparkMsg::ChangeEffect { old_id, new_id } => {
+                change_effect(&mut state.current, old_id, new_id);
+                SparkToAppMsg::Ack { sequence, sub_command: 0x06 }
+            }
+            AppToSparkMsg::SetTuner(enabled) => {
+                state.tuner = *enabled;
+                SparkToAppMsg::Ack { sequence, sub_command: 0x65 }
+            }
+            AppToSparkMsg::Unknown { .. } => return None,
+        };
+        Some(reply)
+    }
+}
+
+impl SimState {
+    fn select(&mut self, preset: u8) {
+        self.hardware_preset = preset;
+        if let Some(stored) = self.presets.get(preset.wrapping_sub(1) as usize) {
+            self.current = stored.clone();
+        }
+    }
+}
+
+fn empty_preset() -> Preset {
+    Preset {
+        slot:        0,
+        uuid:        String::new(),
+        name:        String::new(),
+        version:     String::new(),
+        description: String::new(),
+        icon:        String::new(),
+        bpm:         120.0,
+        pedals:      Vec::new(),
+    }
+}
+
+fn set_parameter(preset: &mut Preset, effect_id: &str, param_index: u8, value: f32) {
+    let param = preset.pedals.iter_mut()
+        .find(|p| p.model == effect_id)
+        .and_then(|p| p.parameters.get_mut(param_index as usize));
+    if let Some(param) = param {
+        *param = value.clamp(0.0, 1.0);
+    }
+}
+
+fn change_effect(preset: &mut Preset, old_id: &str, new_id: &str) {
+    if let Some(pedal) = preset.pedals.iter_mut().find(|p| p.model == old_id) {
+        pedal.model = new_id.into();
+    }
+}
+
+// Stand-in for the amp's own checksum, which isn't known. It only needs to
+// change when the preset does.
+fn preset_checksum(preset: &Preset) -> u8 {
+    let msg = AppToSparkMsg::SendPreset(Preset { slot: 0, ..preset.clone() });
+    let mut payload = vec![0; msg.payload_len()];
+    msg.encode_payload(&mut FieldWriter::new(&mut payload));
+    payload.iter().fold(0, |acc, b| acc ^ b) & 0x7F
+}
@@ -0,0 +1,68 @@
+use alloc::collections::VecDeque;
+use zerocopy::FromBytes;
+use crate::message::{BlockHeader, DecodeError, DecoderDiagnostics, SparkBlockFramer, SparkMessage, SparkMsgDecoder, SparkToAppMsg};
+
+// Decodes a raw byte stream, wherever it came from: BLE notifications, a
+// UART or a capture file. Bytes are pushed in slices of any size, blocks are
+// framed out of them and decoded, and complete messages are handed back one
+// at a time. Junk between blocks and blocks cut short are skipped.
+//
+// Blocks travelling the other way are passed over, so a capture of both
+// directions can be fed to one stream decoder per direction.
+pub struct SparkStreamDecoder<M = SparkToAppMsg> {
+    framer:  SparkBlockFramer,
+    decoder: SparkMsgDecoder<M>,
+    ready:   VecDeque<Result<(u8, M), DecodeError>>,
+}
+
+impl<M> Default for SparkStreamDecoder<M> {
+    fn default() -> Self {
+        SparkStreamDecoder {
+            framer:  SparkBlockFramer::new(),
+            decoder: SparkMsgDecoder::default(),
+            ready:   VecDeque::new(),
+        }
+    }
+}
+
+impl SparkStreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: SparkMessage> SparkStreamDecoder<M> {
+    pub fn push(&mut self, data: &[u8]) {
+        self.framer.push(data);
+    }
+
+    // Returns the next decoded message or error, or None until more bytes
+    // are pushed.
+    pub fn next_message(&mut self) -> Option<Result<M, DecodeError>> {
+        Some(self.next_with_sequence()?.map(|(_, msg)| msg))
+    }
+
+    // Like next_message, with the message's sequence number alongside it
+    pub fn next_with_sequence(&mut self) -> Option<Result<(u8, M), DecodeError>> {
+        while self.ready.is_empty() {
+            let block = self.framer.next_block()?;
+
+            // The framer only hands back blocks with a valid header
+            let ours = BlockHeader::read_from_prefix(&block)
+                .is_ok_and(|(hdr, _)| hdr.direction.get() == M::DIRECTION as u16);
+            if ours {
+                self.ready.extend(self.decoder.decode_with_sequence(&block));
+            }
+        }
+        self.ready.pop_front()
+    }
+
+    pub fn diagnostics(&self) -> DecoderDiagnostics {
+        self.decoder.diagnostics()
+    }
+
+    // Bytes thrown away so far while looking for a block
+    pub fn skipped_bytes(&self) -> usize {
+        self.framer.skipped_bytes()
+    }
+}
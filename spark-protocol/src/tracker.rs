@@ -0,0 +1,138 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::message::{AppToSparkMsg, SparkMessage, SparkToAppMsg};
+
+// Ties replies from the amp back to the request that caused them. The amp
+// answers a command with a 0x04 acknowledgement and a query with a 0x03
+// response, both carrying the request's sequence number and sub-command.
+//
+// Time is passed in as milliseconds so this stays independent of the timer.
+pub struct RequestTracker {
+    timeout_ms:  u64,
+    max_retries: u8,
+    pending:     Vec<PendingRequest>,
+}
+
+struct PendingRequest {
+    sequence: u8,
+    request:  AppToSparkMsg,
+    blocks:   Vec<Vec<u8>>,
+    sent_at:  u64,
+    retries:  u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestError {
+    // No reply after the initial send and every retry
+    TimedOut,
+}
+
+pub struct RequestResult {
+    pub sequence: u8,
+    pub request:  AppToSparkMsg,
+    pub result:   Result<SparkToAppMsg, RequestError>,
+}
+
+pub enum TrackerEvent {
+    // Write these blocks again, unchanged, so the reply keeps the same sequence
+    Resend { sequence: u8, blocks: Vec<Vec<u8>> },
+    Finished(Box<RequestResult>),
+}
+
+impl RequestTracker {
+    pub fn new(timeout_ms: u64, max_retries: u8) -> Self {
+        RequestTracker {
+            timeout_ms,
+            max_retries,
+            pending: Vec::new(),
+        }
+    }
+
+    // Starts tracking a request that was just written as `blocks`.
+    pub fn track(&mut self, sequence: u8, request: AppToSparkMsg, blocks: Vec<Vec<u8>>, now_ms: u64) {
+        // Sequence numbers wrap, anything still waiting on this one is stale
+        self.pending.retain(|p| p.sequence != sequence);
+        self.pending.push(PendingRequest {
+            sequence,
+            request,
+            blocks,
+            sent_at: now_ms,
+            retries: 0,
+        });
+    }
+
+    // Stops tracking a request, e.g. one that couldn't be written.
+    pub fn cancel(&mut self, sequence: u8) {
+        self.pending.retain(|p| p.sequence != sequence);
+    }
+
+    // Sets when a tracked request was written, for one tracked before the
+    // time was known. Its retries are left as they are.
+    pub fn stamp(&mut self, sequence: u8, now_ms: u64) {
+        if let Some(pending) = self.pending.iter_mut().find(|p| p.sequence == sequence) {
+            pending.sent_at = now_ms;
+        }
+    }
+
+    pub fn is_pending(&self, sequence: u8) -> bool {
+        self.pending.iter().any(|p| p.sequence == sequence)
+    }
+
+    // Offers a decoded message. Returns the request it completes, if any.
+    pub fn on_message(&mut self, msg: &SparkToAppMsg) -> Option<RequestResult> {
+        let (command, sub_command) = msg.opcode();
+
+        // Events share sub-commands with our commands, so the command byte
+        // has to match too
+        let slot = self.pending.iter().position(|p| {
+            let (request_command, request_sub_command) = p.request.opcode();
+            let reply_command = match request_command {
+                0x01 => 0x04,
+                0x02 => 0x03,
+                _ => return false,
+            };
+            p.sequence == msg.sequence() && reply_command == command && request_sub_command == sub_command
+        })?;
+
+        let pending = self.pending.swap_remove(slot);
+        Some(RequestResult {
+            sequence: pending.sequence,
+            request:  pending.request,
+            result:   Ok(msg.clone()),
+        })
+    }
+
+    // Resends requests whose timeout has passed and gives up on those that
+    // have used all their retries.
+    pub fn poll(&mut self, now_ms: u64) -> Vec<TrackerEvent> {
+        let mut events = Vec::new();
+
+        let mut i = 0;
+        while i < self.pending.len() {
+            let pending = &mut self.pending[i];
+            if now_ms.saturating_sub(pending.sent_at) < self.timeout_ms {
+                i += 1;
+                continue;
+            }
+
+            if pending.retries < self.max_retries {
+                pending.retries += 1;
+                pending.sent_at = now_ms;
+                events.push(TrackerEvent::Resend {
+                    sequence: pending.sequence,
+                    blocks:   pending.blocks.clone(),
+                });
+                i += 1;
+            } else {
+                let pending = self.pending.swap_remove(i);
+                events.push(TrackerEvent::Finished(Box::new(RequestResult {
+                    sequence: pending.sequence,
+                    request:  pending.request,
+                    result:   Err(RequestError::TimedOut),
+                })));
+            }
+        }
+
+        events
+    }
+}
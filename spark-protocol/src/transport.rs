@@ -0,0 +1,119 @@
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    Connected,
+    Disconnected,
+}
+
+// The link to an amp, or to anything else that speaks the protocol. Blocks
+// go out whole, bytes come back in whatever pieces the link delivers them,
+// ready for a SparkStreamDecoder.
+//
+// Everything takes &self so one task can receive while others send. Only
+// one task should be receiving at a time.
+#[allow(async_fn_in_trait)]
+pub trait SparkTransport {
+    type Error: core::fmt::Debug;
+
+    // Writes one encoded block
+    async fn send(&self, block: &[u8]) -> Result<(), Self::Error>;
+
+    // Waits for bytes from the other end and copies them into `buf`,
+    // returning how many were copied.
+    async fn receive(&self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    fn link_state(&self) -> LinkState;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopbackError {
+    Disconnected,
+}
+
+// Bytes in flight one way, and whoever is waiting for them
+#[derive(Default)]
+struct Pipe {
+    bytes:  VecDeque<u8>,
+    waker:  Option<Waker>,
+    closed: bool,
+}
+
+impl Pipe {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// One end of an in-memory link, for running both sides of the protocol in
+// one process. Whatever one end sends, the other receives.
+pub struct LoopbackTransport {
+    inbox:  Rc<RefCell<Pipe>>,
+    outbox: Rc<RefCell<Pipe>>,
+}
+
+impl LoopbackTransport {
+    pub fn pair() -> (Self, Self) {
+        let a = Rc::new(RefCell::new(Pipe::default()));
+        let b = Rc::new(RefCell::new(Pipe::default()));
+        (
+            LoopbackTransport { inbox: a.clone(), outbox: b.clone() },
+            LoopbackTransport { inbox: b, outbox: a },
+        )
+    }
+
+    // Drops the link for both ends, as if the amp went out of range. Bytes
+    // already sent can still be received.
+    pub fn disconnect(&self) {
+        for pipe in [&self.inbox, &self.outbox] {
+            let mut pipe = pipe.borrow_mut();
+            pipe.closed = true;
+            pipe.wake();
+        }
+    }
+}
+
+impl SparkTransport for LoopbackTransport {
+    type Error = LoopbackError;
+
+    async fn send(&self, block: &[u8]) -> Result<(), LoopbackError> {
+        let mut pipe = self.outbox.borrow_mut();
+        if pipe.closed { return Err(LoopbackError::Disconnected); }
+
+        pipe.bytes.extend(block);
+        pipe.wake();
+        Ok(())
+    }
+
+    async fn receive(&self, buf: &mut [u8]) -> Result<usize, LoopbackError> {
+        poll_fn(|cx| {
+            let mut pipe = self.inbox.borrow_mut();
+            if !pipe.bytes.is_empty() {
+                let len = pipe.bytes.len().min(buf.len());
+                for (slot, b) in buf.iter_mut().zip(pipe.bytes.drain(..len)) {
+                    *slot = b;
+                }
+                return Poll::Ready(Ok(len));
+            }
+            if pipe.closed { return Poll::Ready(Err(LoopbackError::Disconnected)); }
+
+            pipe.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+
+    fn link_state(&self) -> LinkState {
+        if self.inbox.borrow().closed {
+            LinkState::Disconnected
+        } else {
+            LinkState::Connected
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use spark_protocol::amp::*;
+use spark_protocol::message::*;
+
+#[test]
+fn detects_models_from_advertised_names() {
+    assert_eq!(AmpModel::from_name("Spark 40 Audio"), AmpModel::Spark40);
+    assert_eq!(AmpModel::from_name("Spark MINI BLE"), AmpModel::SparkMini);
+    assert_eq!(AmpModel::from_name("Spark GO"), AmpModel::SparkGo);
+    assert_eq!(AmpModel::from_name("Spark 2"), AmpModel::Spark2);
+}
+
+#[test]
+fn detects_models_from_amp_names() {
+    assert_eq!(AmpModel::from_name("spark 40"), AmpModel::Spark40);
+    assert_eq!(AmpModel::from_name("Spark Mini"), AmpModel::SparkMini);
+    assert_eq!(AmpModel::from_name("SPARK GO BLE"), AmpModel::SparkGo);
+    assert_eq!(AmpModel::from_name("Spark 2 Audio"), AmpModel::Spark2);
+    assert_eq!(AmpModel::from_name("Spark"), AmpModel::Unknown);
+    assert_eq!(AmpModel::from_name(""), AmpModel::Unknown);
+}
+
+#[test]
+fn profiles_limit_hardware_presets() {
+    for (model, hardware_presets) in [
+        (AmpModel::Spark40, 4),
+        (AmpModel::SparkMini, 4),
+        (AmpModel::SparkGo, 4),
+        (AmpModel::Spark2, 8),
+        (AmpModel::Unknown, 4),
+    ] {
+        let profile = model.profile();
+        assert_eq!(profile.hardware_presets, hardware_presets, "{:?}", model);
+
+        assert_eq!(profile.validate(&AppToSparkMsg::SetHardwarePreset(0)), Err(CommandError::InvalidPresetSlot(0)));
+        assert_eq!(profile.validate(&AppToSparkMsg::SetHardwarePreset(hardware_presets)), Ok(()));
+        assert_eq!(
+            profile.validate(&AppToSparkMsg::SetHardwarePreset(hardware_presets + 1)),
+            Err(CommandError::InvalidPresetSlot(hardware_presets + 1))
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use spark_protocol::catalog::*;
+
+#[test]
+fn finds_effects_by_id() {
+    let effect = find("DistortionTS9").unwrap();
+    assert_eq!(effect.name, "Tube Drive");
+    assert_eq!(effect.category, Category::Drive);
+    assert_eq!(effect.param(0).map(|p| p.name), Some("Overdrive"));
+    assert!(effect.param(3).is_none());
+
+    assert_eq!(find("Twin").map(|e| e.category), Some(Category::Amp));
+    assert!(find("twin").is_none());
+    assert!(find("").is_none());
+}
+
+#[test]
+fn ids_are_unique() {
+    for (i, effect) in EFFECTS.iter().enumerate() {
+        assert!(
+            EFFECTS[i + 1..].iter().all(|e| e.id != effect.id),
+            "{} is listed twice", effect.id
+        );
+        assert!(core::ptr::eq(find(effect.id).unwrap(), effect));
+    }
+}
+
+#[test]
+fn scales_values_for_display() {
+    let gain = find("Twin").unwrap().param(0).unwrap();
+    assert_eq!(gain.display_value(0.0), 0.0);
+    assert_eq!(gain.display_value(0.5), 5.0);
+    assert_eq!(gain.display_value(1.0), 10.0);
+    // Out of range values are clamped
+    assert_eq!(gain.display_value(1.5), 10.0);
+    assert_eq!(gain.display_value(-0.5), 0.0);
+    assert_eq!(gain.choice(0.5), None);
+
+    let threshold = find("bias.noisegate").unwrap().param(0).unwrap();
+    assert_eq!((threshold.display_value(0.5), threshold.unit.suffix()), (-45.0, "dB"));
+
+    let cut = find("bias.reverb").unwrap().param(5).unwrap();
+    assert_eq!((cut.display_value(0.0), cut.display_value(1.0)), (1000.0, 20000.0));
+}
+
+#[test]
+fn names_switch_positions() {
+    let bpm = find("DelayMono").unwrap().param(4).unwrap();
+    assert_eq!(bpm.name, "BPM");
+    assert_eq!(bpm.choice(0.0), Some("Off"));
+    assert_eq!(bpm.choice(0.4), Some("Off"));
+    assert_eq!(bpm.choice(0.6), Some("On"));
+    assert_eq!(bpm.choice(1.0), Some("On"));
+
+    let reverb_type = find("bias.reverb").unwrap().param(6).unwrap();
+    assert_eq!(reverb_type.choice(0.0), Some("Room Studio A"));
+    assert_eq!(reverb_type.choice(0.5), Some("Hall Medium"));
+    assert_eq!(reverb_type.display_value(0.5), 4.0);
+    assert_eq!(reverb_type.choice(1.0), Some("Plate Long"));
+}
+
+#[test]
+fn params_are_well_formed() {
+    for effect in EFFECTS {
+        assert!(!effect.params.is_empty(), "{} has no parameters", effect.id);
+        for p in effect.params {
+            assert!(p.min < p.max, "{} {} has an empty range", effect.id, p.name);
+            if !p.choices.is_empty() {
+                assert_eq!((p.min, p.max), (0.0, (p.choices.len() - 1) as f32), "{} {}", effect.id, p.name);
+            }
+        }
+    }
+}
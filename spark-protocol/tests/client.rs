@@ -0,0 +1,191 @@
+mod common;
+
+use core::future::Future;
+use embassy_futures::{block_on, yield_now};
+use embassy_futures::join::{join, join3};
+use common::{preset, stored_presets};
+use spark_protocol::amp::{AmpModel, CommandError};
+use spark_protocol::client::*;
+use spark_protocol::message::*;
+use spark_protocol::sim::*;
+use spark_protocol::transport::*;
+
+type Client = SparkClient<LoopbackTransport>;
+
+// A client connected to a simulated Spark 40
+fn connect(model: AmpModel) -> (Client, SimulatedAmp<LoopbackTransport>) {
+    let (app, amp) = LoopbackTransport::pair();
+    let amp = SimulatedAmp::new(amp, SimIdentity::spark_40(), stored_presets());
+    (SparkClient::new(app, model, 1000, 2), amp)
+}
+
+// Runs both ends until `test` finishes, then drops the link so they stop
+fn run_until(client: &Client, amp: &SimulatedAmp<LoopbackTransport>, test: impl Future<Output = ()>) {
+    block_on(async {
+        let (ran, answered, _) = join3(client.run(), amp.run(), async {
+            test.await;
+            client.transport().disconnect();
+        })
+        .await;
+        assert_eq!(ran, Ok(()));
+        assert_eq!(answered, Ok(()));
+    });
+}
+
+// Gives the amp a chance to handle whatever was just sent
+async fn settle() {
+    for _ in 0..8 {
+        yield_now().await;
+    }
+}
+
+#[test]
+fn queries_the_amp() {
+    let (client, amp) = connect(AmpModel::Unknown);
+    run_until(&client, &amp, async {
+        let identity = SimIdentity::spark_40();
+        assert_eq!(client.amp_name().await, Ok(identity.name));
+        assert_eq!(client.model(), AmpModel::Spark40);
+        assert_eq!(client.serial_number().await, Ok(identity.serial));
+        assert_eq!(client.firmware_version().await, Ok(identity.firmware));
+        assert_eq!(client.preset_checksums().await.map(|c| c.len()), Ok(4));
+        assert_eq!(client.selected_hardware_preset().await, Ok(1));
+        assert_eq!(client.current_preset().await, Ok(stored_presets().remove(0)));
+    });
+}
+
+#[test]
+fn sends_commands() {
+    let (client, amp) = connect(AmpModel::Spark40);
+    run_until(&client, &amp, async {
+        assert_eq!(client.select_hardware_preset(3).await, Ok(()));
+        assert_eq!(client.toggle_effect("Booster", false).await, Ok(()));
+        assert_eq!(client.set_parameter("RolandJC120", 0, 0.8).await, Ok(()));
+        assert_eq!(client.change_effect("Cloner", "Flanger").await, Ok(()));
+        assert_eq!(client.set_tuner(true).await, Ok(()));
+
+        let current = client.current_preset().await.unwrap();
+        assert_eq!(current, amp.current_preset());
+        assert_eq!(current.name, "Preset 3");
+        assert!(!current.pedals[2].enabled);
+        assert_eq!(current.pedals[3].parameters[0], 0.8);
+        assert_eq!(current.pedals[4].model, "Flanger");
+        assert!(amp.tuner_enabled());
+
+        let edited = Preset { slot: 1, name: "Edited".into(), ..preset() };
+        assert_eq!(client.send_preset(edited.clone()).await, Ok(()));
+        assert_eq!(amp.stored_preset(1), Some(edited));
+    });
+}
+
+#[test]
+fn delivers_events_alongside_replies() {
+    let (client, amp) = connect(AmpModel::Spark40);
+    run_until(&client, &amp, async {
+        let knob = SparkToAppMsg::ParameterChanged { sequence: 0x30, effect_id: "Booster".into(), param_index: 0, value: 0.4 };
+        let button = SparkToAppMsg::HardwarePresetChanged { sequence: 0x31, preset: 2 };
+
+        // Nothing is kept before subscribing
+        let missed = SparkToAppMsg::EffectToggled { sequence: 0x2F, effect_id: "Booster".into(), enabled: false };
+        amp.send_event(missed).await.unwrap();
+        assert_eq!(client.amp_name().await.map(|_| ()), Ok(()));
+
+        let mut events = client.subscribe_events();
+        amp.script(ScriptedEvent { after_requests: 2, event: knob.clone() });
+        amp.script(ScriptedEvent { after_requests: 2, event: button.clone() });
+        assert_eq!(client.selected_hardware_preset().await, Ok(1));
+        assert_eq!(events.next().await, knob);
+        assert_eq!(events.next().await, button);
+        assert_eq!(client.selected_hardware_preset().await, Ok(2));
+    });
+}
+
+#[test]
+fn keeps_events_that_collide_with_requests() {
+    let (client, amp) = connect(AmpModel::Spark40);
+    run_until(&client, &amp, async {
+        let mut events = client.subscribe_events();
+
+        // Buttons pressed on the amp just as the app asks for the same
+        // thing, with the sequence numbers of the app's requests
+        let button = SparkToAppMsg::HardwarePresetChanged { sequence: 0, preset: 2 };
+        let footswitch = SparkToAppMsg::EffectToggled { sequence: 1, effect_id: "Booster".into(), enabled: false };
+        amp.script(ScriptedEvent { after_requests: 0, event: button.clone() });
+        amp.script(ScriptedEvent { after_requests: 1, event: footswitch.clone() });
+
+        assert_eq!(client.select_hardware_preset(3).await, Ok(()));
+        assert_eq!(client.toggle_effect("Booster", true).await, Ok(()));
+        assert_eq!(events.next().await, button);
+        assert_eq!(events.next().await, footswitch);
+        assert_eq!(client.selected_hardware_preset().await, Ok(3));
+    });
+}
+
+#[test]
+fn reports_errors() {
+    let (client, amp) = connect(AmpModel::Spark40);
+    run_until(&client, &amp, async {
+        assert_eq!(
+            client.select_hardware_preset(5).await,
+            Err(ClientError::Command(CommandError::InvalidPresetSlot(5)))
+        );
+        assert_eq!(amp.requests_handled(), 0);
+
+        // The amp never answers an opcode it doesn't know
+        let unknown = AppToSparkMsg::Unknown { command: 0x02, sub_command: 0x99, payload: vec![] };
+        let (result, _) = join(client.request(unknown), async {
+            for now_ms in [500, 1500, 2500, 3500] {
+                client.tick(now_ms).await.unwrap();
+            }
+        })
+        .await;
+        assert_eq!(result, Err(ClientError::TimedOut));
+        // The initial send and both retries
+        assert_eq!(amp.requests_handled(), 3);
+
+        // Still usable afterwards
+        assert_eq!(client.selected_hardware_preset().await, Ok(1));
+    });
+}
+
+#[test]
+fn times_requests_from_the_next_tick() {
+    let (client, amp) = connect(AmpModel::Spark40);
+    run_until(&client, &amp, async {
+        // Sent long after the clock started, before any tick has passed it in
+        let unknown = AppToSparkMsg::Unknown { command: 0x02, sub_command: 0x99, payload: vec![] };
+        let (result, _) = join(client.request(unknown), async {
+            client.tick(15_000).await.unwrap();
+            client.tick(15_999).await.unwrap();
+            settle().await;
+            assert_eq!(amp.requests_handled(), 1);
+
+            client.tick(16_000).await.unwrap();
+            settle().await;
+            assert_eq!(amp.requests_handled(), 2);
+
+            for now_ms in [17_000, 18_000] {
+                client.tick(now_ms).await.unwrap();
+            }
+        })
+        .await;
+        assert_eq!(result, Err(ClientError::TimedOut));
+        assert_eq!(amp.requests_handled(), 3);
+    });
+}
+
+#[test]
+fn takes_the_preset_count_from_the_amp() {
+    // Advertised as a Spark 2, but the amp answering has four slots
+    let (client, amp) = connect(AmpModel::Spark2);
+    run_until(&client, &amp, async {
+        assert_eq!(client.profile().hardware_presets, 8);
+        assert_eq!(client.preset_checksums().await.map(|c| c.len()), Ok(4));
+        assert_eq!(client.profile().hardware_presets, 4);
+        assert_eq!(
+            client.select_hardware_preset(6).await,
+            Err(ClientError::Command(CommandError::InvalidPresetSlot(6)))
+        );
+        assert_eq!(client.select_hardware_preset(4).await, Ok(()));
+    });
+}
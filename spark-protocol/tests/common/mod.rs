@@ -0,0 +1,36 @@
+// Shared by every test binary, each of which uses only some of it
+#![allow(dead_code)]
+
+use spark_protocol::message::*;
+
+// A full seven pedal preset, long enough to need several chunks
+pub fn preset() -> Preset {
+    let pedal = |model: &str, params: usize| Pedal {
+        model:      model.into(),
+        enabled:    true,
+        parameters: (0..params).map(|i| i as f32 / 10.0).collect(),
+    };
+    Preset {
+        slot:        CURRENT_PRESET_SLOT,
+        uuid:        "07079063-94A9-41B1-AB1D-02CBC5D00790".into(),
+        name:        "Silver Ship".into(),
+        version:     "0.7".into(),
+        description: "1-Clean and smooth, long enough to need several chunks".into(),
+        icon:        "icon.png".into(),
+        bpm:         120.0,
+        pedals:      vec![
+            pedal("bias.noisegate", 3),
+            pedal("LA2AComp", 3),
+            pedal("Booster", 1),
+            pedal("RolandJC120", 5),
+            pedal("Cloner", 2),
+            pedal("VintageDelay", 4),
+            pedal("bias.reverb", 7),
+        ],
+    }
+}
+
+// Presets for all four hardware slots, named after their buttons
+pub fn stored_presets() -> Vec<Preset> {
+    (0..4).map(|slot| Preset { slot, name: format!("Preset {}", slot + 1), ..preset() }).collect()
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use common::preset;
+use spark_protocol::message::*;
+
+// Blocks as they appear on the wire, one chunk each.
+
+const GET_AMP_NAME: [u8; 23] = [
+    0x01, 0xFE, 0x00, 0x00, 0x53, 0xFE, 0x17, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x00, 0x00, 0x02, 0x11, 0xF7,
+];
+
+// SetHardwarePreset(2), sequence 1
+const SET_HARDWARE_PRESET: [u8; 26] = [
+    0x01, 0xFE, 0x00, 0x00, 0x53, 0xFE, 0x1A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x01, 0x01, 0x01, 0x38, 0x00, 0x00, 0x01, 0xF7,
+];
+
+// Effect ids go out as a length byte followed by a short string, the layout
+// the public SparkIO reference uses. These three were assembled by hand from
+// that layout rather than by this encoder.
+
+// ToggleEffect("DistortionTS9", false), sequence 2
+const TOGGLE_EFFECT: [u8; 43] = [
+    0x01, 0xFE, 0x00, 0x00, 0x53, 0xFE, 0x2B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x02, 0x77, 0x01, 0x15, 0x02, 0x0D, 0x2D, 0x44, 0x69, 0x73, 0x74, 0x6F, 0x00, 0x72,
+    0x74, 0x69, 0x6F, 0x6E, 0x54, 0x53, 0x02, 0x39, 0x42, 0x00, 0xF7,
+];
+
+// SetParameter("Twin", 2, 0.5), sequence 3
+const SET_PARAMETER: [u8; 37] = [
+    0x01, 0xFE, 0x00, 0x00, 0x53, 0xFE, 0x25, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x03, 0x70, 0x01, 0x04, 0x02, 0x04, 0x24, 0x54, 0x77, 0x69, 0x6E, 0x02, 0x01, 0x4A,
+    0x3F, 0x00, 0x00, 0x00, 0xF7,
+];
+
+// ChangeEffect("DistortionTS9", "Overdrive"), sequence 4
+const CHANGE_EFFECT: [u8; 53] = [
+    0x01, 0xFE, 0x00, 0x00, 0x53, 0xFE, 0x35, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x04, 0x51, 0x01, 0x06, 0x02, 0x0D, 0x2D, 0x44, 0x69, 0x73, 0x74, 0x6F, 0x00, 0x72,
+    0x74, 0x69, 0x6F, 0x6E, 0x54, 0x53, 0x04, 0x39, 0x09, 0x29, 0x4F, 0x76, 0x65, 0x72, 0x00, 0x64,
+    0x72, 0x69, 0x76, 0x65, 0xF7,
+];
+
+// AmpName "Spark 40 Audio", sequence 5
+const AMP_NAME: [u8; 42] = [
+    0x01, 0xFE, 0x00, 0x00, 0x41, 0xFF, 0x2A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x05, 0x2B, 0x03, 0x11, 0x02, 0x0E, 0x2E, 0x53, 0x70, 0x61, 0x72, 0x6B, 0x00, 0x20,
+    0x34, 0x30, 0x20, 0x41, 0x75, 0x64, 0x00, 0x69, 0x6F, 0xF7,
+];
+
+// TunerReading for an A, 25 cents sharp
+const TUNER_READING: [u8; 30] = [
+    0x01, 0xFE, 0x00, 0x00, 0x41, 0xFF, 0x1E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x60, 0x3E, 0x03, 0x64, 0x02, 0x09, 0x4A, 0x3F, 0x40, 0x00, 0x00, 0xF7,
+];
+
+// Knob and footswitch events, effect ids prefixed the same way as in the
+// app's commands. Also assembled by hand from the SparkIO layout.
+
+// ParameterChanged("Twin", 1, 0.75)
+const PARAMETER_CHANGED: [u8; 37] = [
+    0x01, 0xFE, 0x00, 0x00, 0x41, 0xFF, 0x25, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x61, 0x33, 0x03, 0x37, 0x02, 0x04, 0x24, 0x54, 0x77, 0x69, 0x6E, 0x01, 0x01, 0x4A,
+    0x3F, 0x40, 0x00, 0x00, 0xF7,
+];
+
+// EffectToggled("DistortionTS9", true)
+const EFFECT_TOGGLED: [u8; 42] = [
+    0x01, 0xFE, 0x00, 0x00, 0x41, 0xFF, 0x2A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xF0, 0x01, 0x62, 0x76, 0x03, 0x15, 0x02, 0x0D, 0x2D, 0x44, 0x69, 0x73, 0x74, 0x6F, 0x00, 0x72,
+    0x74, 0x69, 0x6F, 0x6E, 0x54, 0x53, 0x02, 0x39, 0x43, 0xF7,
+];
+
+fn decode_one(block: &[u8]) -> Result<SparkToAppMsg, DecodeError> {
+    let mut decoder = SparkMsgDecoder::new();
+    let mut msgs = decoder.decode(block);
+    assert_eq!(msgs.len(), 1);
+    msgs.remove(0)
+}
+
+#[test]
+fn encodes_golden_blocks() {
+    let mut encoder = SparkMsgEncoder::new();
+
+    assert_eq!(encoder.encode(AppToSparkMsg::GetAmpName).unwrap(), [GET_AMP_NAME.to_vec()]);
+    assert_eq!(encoder.encode(AppToSparkMsg::SetHardwarePreset(2)).unwrap(), [SET_HARDWARE_PRESET.to_vec()]);
+    assert_eq!(
+        encoder.encode(AppToSparkMsg::ToggleEffect { effect_id: "DistortionTS9".into(), enabled: false }).unwrap(),
+        [TOGGLE_EFFECT.to_vec()]
+    );
+    assert_eq!(
+        encoder.encode(AppToSparkMsg::SetParameter { effect_id: "Twin".into(), param_index: 2, value: 0.5 }).unwrap(),
+        [SET_PARAMETER.to_vec()]
+    );
+    assert_eq!(
+        encoder.encode(AppToSparkMsg::ChangeEffect { old_id: "DistortionTS9".into(), new_id: "Overdrive".into() }).unwrap(),
+        [CHANGE_EFFECT.to_vec()]
+    );
+    assert_eq!(encoder.next_sequence(), 5);
+}
+
+#[test]
+fn decodes_golden_blocks() {
+    match decode_one(&AMP_NAME) {
+        Ok(SparkToAppMsg::AmpName { sequence: 5, name }) => assert_eq!(name, "Spark 40 Audio"),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    match decode_one(&TUNER_READING) {
+        Ok(SparkToAppMsg::TunerReading { sequence: 0x60, note, offset }) => {
+            assert_eq!(NOTE_NAMES[note as usize], "A");
+            assert_eq!(offset, 0.75);
+            assert_eq!(tuner_cents(offset), 25.0);
+        },
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(
+        decode_one(&PARAMETER_CHANGED),
+        Ok(SparkToAppMsg::ParameterChanged { sequence: 0x61, effect_id: "Twin".into(), param_index: 1, value: 0.75 })
+    );
+    assert_eq!(
+        decode_one(&EFFECT_TOGGLED),
+        Ok(SparkToAppMsg::EffectToggled { sequence: 0x62, effect_id: "DistortionTS9".into(), enabled: true })
+    );
+}
+
+#[test]
+fn rejects_corrupt_blocks() {
+    let mut bad_checksum = AMP_NAME;
+    bad_checksum[19] ^= 0x01;
+    assert_eq!(
+        decode_one(&bad_checksum).unwrap_err(),
+        DecodeError::BadChecksum { command: 0x03, sub_command: 0x11 }
+    );
+
+    // Our own requests echoed back are the wrong direction
+    let mut decoder = SparkMsgDecoder::new();
+    assert_eq!(decoder.decode(&GET_AMP_NAME), [Err(DecodeError::WrongDirection)]);
+
+    let mut decoder = SparkMsgDecoder::new();
+    assert_eq!(decoder.decode(&AMP_NAME[..20]), [Err(DecodeError::TruncatedBlock)]);
+}
+
+#[test]
+fn frames_blocks_across_writes() {
+    let mut stream = vec![0x12, 0x01, 0xFE];
+    stream.extend_from_slice(&AMP_NAME[..10]);
+    stream.extend_from_slice(&AMP_NAME);
+    stream.extend_from_slice(&TUNER_READING);
+
+    let mut framer = SparkBlockFramer::new();
+    let mut blocks = Vec::new();
+    for piece in stream.chunks(7) {
+        framer.push(piece);
+        while let Some(block) = framer.next_block() {
+            blocks.push(block);
+        }
+    }
+
+    assert_eq!(blocks, [AMP_NAME.to_vec(), TUNER_READING.to_vec()]);
+}
+
+#[test]
+fn encodes_into_fixed_buffers() {
+    let msg = AppToSparkMsg::SendPreset(preset());
+    let expected = SparkMsgEncoder::new().encode(msg.clone()).unwrap();
+    assert!(expected.len() > 1);
+
+    let mut encoder = SparkMsgEncoder::new();
+    let mut small = [0u8; 64];
+    assert_eq!(
+        encoder.encode_into(&msg, &mut small).err(),
+        Some(EncodeError::BufferTooSmall { needed: msg.payload_len() })
+    );
+    assert_eq!(encoder.next_sequence(), 0);
+
+    let mut payload = [0u8; 1024];
+    let blocks: Vec<Block> = encoder.encode_into(&msg, &mut payload).unwrap().collect();
+    assert!(blocks.iter().all(|block| block.len() <= MAX_BLOCK_SIZE));
+    assert_eq!(blocks.iter().map(|block| block.to_vec()).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn decodes_in_place() {
+    let mut decoder = SparkMsgDecoder::new();
+    let mut block = AMP_NAME;
+    let mut payloads = Vec::new();
+    decoder.decode_in_place(&mut block, |result| {
+        let raw = result.unwrap();
+        assert_eq!((raw.sequence, raw.command, raw.sub_command), (5, 0x03, 0x11));
+        payloads.push(raw.payload.to_vec());
+    });
+
+    let mut expected = vec![0x0E, 0xAE];
+    expected.extend_from_slice(b"Spark 40 Audio");
+    assert_eq!(payloads, [expected.clone()]);
+
+    let raw = RawMessage { sequence: 5, command: 0x03, sub_command: 0x11, payload: &expected };
+    assert_eq!(decoder.parse(&raw), Ok(SparkToAppMsg::AmpName { sequence: 5, name: "Spark 40 Audio".into() }));
+
+    let raw = RawMessage { payload: &expected[..4], ..raw };
+    assert_eq!(decoder.parse(&raw), Err(DecodeError::CorruptPayload { command: 0x03, sub_command: 0x11 }));
+    assert_eq!(
+        decoder.diagnostics(),
+        DecoderDiagnostics { messages: 1, checksum_errors: 0, other_errors: 1 }
+    );
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use core::fmt::Debug;
+use common::preset;
+use spark_protocol::field::{FieldWriter, SparkField};
+use spark_protocol::message::*;
+
+fn round_trip<M: SparkMessage + Clone + PartialEq + Debug>(msgs: &[M]) {
+    let mut encoder = SparkMsgEncoder::<M>::default();
+    let mut decoder = SparkMsgDecoder::<M>::default();
+
+    for msg in msgs {
+        let mut decoded = Vec::new();
+        for block in encoder.encode(msg.clone()).unwrap() {
+            decoded.extend(decoder.decode(&block));
+        }
+        assert_eq!(decoded, [Ok(msg.clone())]);
+    }
+}
+
+#[test]
+fn app_to_spark_round_trips() {
+    round_trip(&[
+        AppToSparkMsg::GetAmpName,
+        AppToSparkMsg::GetSerialNumber,
+        AppToSparkMsg::GetFirmwareVersion,
+        AppToSparkMsg::GetPresetChecksums,
+        AppToSparkMsg::GetCurrentPreset,
+        AppToSparkMsg::GetSelectedHardwarePreset,
+        AppToSparkMsg::SetHardwarePreset(3),
+        AppToSparkMsg::SendPreset(preset()),
+        AppToSparkMsg::ToggleEffect { effect_id: "DistortionTS9".into(), enabled: true },
+        AppToSparkMsg::SetParameter { effect_id: "Twin".into(), param_index: 4, value: 0.25 },
+        AppToSparkMsg::ChangeEffect { old_id: "Twin".into(), new_id: "RolandJC120".into() },
+        AppToSparkMsg::SetTuner(true),
+        AppToSparkMsg::Unknown { command: 0x02, sub_command: 0x99, payload: vec![0x01, 0x80, 0xFF] },
+    ]);
+}
+
+#[test]
+fn spark_to_app_round_trips() {
+    round_trip(&[
+        SparkToAppMsg::AmpName { sequence: 1, name: "Spark 40 Audio".into() },
+        SparkToAppMsg::Preset { sequence: 2, preset: preset() },
+        SparkToAppMsg::SerialNumber { sequence: 3, serial: "S40D123456789".into() },
+        SparkToAppMsg::FirmwareVersion {
+            sequence: 4,
+            version:  FirmwareVersion { major: 1, minor: 10, patch: 7, build: 103 },
+        },
+        SparkToAppMsg::PresetChecksums { sequence: 5, checksums: vec![0x12, 0xFE, 0x00, 0x80] },
+        SparkToAppMsg::Ack { sequence: 6, sub_command: 0x15 },
+        SparkToAppMsg::Ack { sequence: 7, sub_command: 0x06 },
+        SparkToAppMsg::ParameterChanged { sequence: 8, effect_id: "Twin".into(), param_index: 2, value: 0.75 },
+        SparkToAppMsg::HardwarePresetChanged { sequence: 9, preset: 4 },
+        SparkToAppMsg::SelectedHardwarePreset { sequence: 10, preset: 1 },
+        SparkToAppMsg::EffectChanged { sequence: 11, old_id: "Twin".into(), new_id: "RolandJC120".into() },
+        SparkToAppMsg::EffectToggled { sequence: 12, effect_id: "Booster".into(), enabled: false },
+        SparkToAppMsg::TunerReading { sequence: 13, note: 9, offset: 0.375 },
+        // Offsets that aren't exact in binary come back bit for bit too
+        SparkToAppMsg::TunerReading { sequence: 13, note: 2, offset: 0.6 },
+        SparkToAppMsg::TunerReading { sequence: 13, note: 11, offset: 0.1 },
+        SparkToAppMsg::Ack { sequence: 14, sub_command: 0x38 },
+        SparkToAppMsg::Unknown { sequence: 15, command: 0x03, sub_command: 0x99, payload: vec![0x01, 0x80, 0xFF] },
+    ]);
+}
+
+#[test]
+fn replies_keep_their_sequence() {
+    let mut encoder = SparkMsgEncoder::<SparkToAppMsg>::default();
+    encoder.encode(SparkToAppMsg::Ack { sequence: 0x42, sub_command: 0x15 }).unwrap();
+    assert_eq!(encoder.next_sequence(), 0);
+
+    let mut decoder = SparkMsgDecoder::<AppToSparkMsg>::default();
+    let mut app = SparkMsgEncoder::new();
+    app.encode(AppToSparkMsg::GetAmpName).unwrap();
+    let mut seen = Vec::new();
+    for mut block in app.encode(AppToSparkMsg::SetTuner(false)).unwrap() {
+        decoder.decode_in_place(&mut block, |result| {
+            let raw = result.unwrap();
+            seen.push((raw.sequence, raw.command, raw.sub_command));
+        });
+    }
+    assert_eq!(seen, [(1, 0x01, 0x65)]);
+}
+
+#[test]
+fn converts_tuner_offsets_to_cents() {
+    for (offset, cents) in [(0.5, 0.0), (0.75, 25.0), (0.0, -50.0), (1.0, 50.0), (0.6, 10.0), (0.1, -40.0), (0.333, -16.7)] {
+        assert!((tuner_cents(offset) - cents).abs() < 0.001, "{} gave {}", offset, tuner_cents(offset));
+    }
+}
+
+#[test]
+fn decoders_reject_the_other_direction() {
+    let mut app = SparkMsgEncoder::new();
+    let mut amp = SparkMsgEncoder::<SparkToAppMsg>::default();
+
+    let request = app.encode(AppToSparkMsg::GetAmpName).unwrap();
+    let reply = amp.encode(SparkToAppMsg::AmpName { sequence: 0, name: "Spark MINI".into() }).unwrap();
+
+    assert_eq!(SparkMsgDecoder::new().decode(&request[0]), [Err(DecodeError::WrongDirection)]);
+    assert_eq!(
+        SparkMsgDecoder::<AppToSparkMsg>::default().decode(&reply[0]),
+        [Err(DecodeError::WrongDirection)]
+    );
+}
+
+#[test]
+fn rejects_payloads_too_long_for_their_opcode() {
+    let mut encoder = SparkMsgEncoder::new();
+
+    // Only presets carry the sub-header that lets a message span chunks
+    let unknown = AppToSparkMsg::Unknown { command: 0x02, sub_command: 0x99, payload: vec![0x01; 200] };
+    assert_eq!(encoder.encode(unknown), Err(EncodeError::TooLong { command: 0x02, sub_command: 0x99 }));
+
+    let toggle = AppToSparkMsg::ToggleEffect { effect_id: "A".repeat(200), enabled: true };
+    let mut payload = [0u8; 1024];
+    assert_eq!(
+        encoder.encode_into(&toggle, &mut payload).err(),
+        Some(EncodeError::TooLong { command: 0x01, sub_command: 0x15 })
+    );
+    assert_eq!(encoder.next_sequence(), 0);
+
+    round_trip(&[AppToSparkMsg::Unknown { command: 0x02, sub_command: 0x99, payload: vec![0x01; 0x80] }]);
+}
+
+#[test]
+fn rejects_strings_too_long_for_their_field() {
+    let mut amp = SparkMsgEncoder::<SparkToAppMsg>::default();
+    let name = SparkToAppMsg::AmpName { sequence: 1, name: "S".repeat(300) };
+    assert_eq!(amp.encode(name), Err(EncodeError::FieldTooLong { command: 0x03, sub_command: 0x11 }));
+
+    let mut app = SparkMsgEncoder::new();
+    let long = AppToSparkMsg::SendPreset(Preset { description: "D".repeat(256), ..preset() });
+    let mut payload = [0u8; 2048];
+    assert_eq!(
+        app.encode_into(&long, &mut payload).err(),
+        Some(EncodeError::FieldTooLong { command: 0x01, sub_command: 0x01 })
+    );
+
+    // 255 bytes is the most a string field holds
+    round_trip(&[AppToSparkMsg::SendPreset(Preset { description: "D".repeat(255), ..preset() })]);
+}
+
+#[test]
+fn rejects_list_counts_the_payload_cannot_hold() {
+    fn payload(preset: &Preset) -> Vec<u8> {
+        let mut buf = vec![0; 1024];
+        let mut w = FieldWriter::new(&mut buf);
+        preset.encode(&mut w);
+        let len = w.len();
+        buf.truncate(len);
+        buf
+    }
+
+    // 0xDC 0xFFFF in place of the last list count, before the checksum byte
+    let no_pedals = payload(&Preset { pedals: vec![], ..preset() });
+    let one_pedal = payload(&Preset { pedals: vec![Pedal { parameters: vec![], ..preset().pedals[0].clone() }], ..preset() });
+
+    let mut decoder = SparkMsgDecoder::new();
+    for mut payload in [no_pedals, one_pedal] {
+        let at = payload.len() - 2;
+        assert_eq!(payload[at], 0x90);
+        payload.splice(at..=at, [0xDC, 0xFF, 0xFF]);
+
+        let raw = RawMessage { sequence: 1, command: 0x03, sub_command: 0x01, payload: &payload };
+        assert_eq!(decoder.parse(&raw), Err(DecodeError::CorruptPayload { command: 0x03, sub_command: 0x01 }));
+    }
+
+    // Parameter indexes are a single byte
+    let mut wide = preset();
+    wide.pedals[0].parameters = vec![0.5; 0x101];
+    assert_eq!(
+        SparkMsgEncoder::new().encode(AppToSparkMsg::SendPreset(wide)),
+        Err(EncodeError::FieldTooLong { command: 0x01, sub_command: 0x01 })
+    );
+}
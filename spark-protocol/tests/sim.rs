@@ -0,0 +1,163 @@
+mod common;
+
+use core::future::Future;
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+use common::{preset, stored_presets};
+use spark_protocol::message::*;
+use spark_protocol::sim::*;
+use spark_protocol::stream::SparkStreamDecoder;
+use spark_protocol::transport::*;
+
+// The app end of the link, just enough to drive the amp by hand
+struct App {
+    link:    LoopbackTransport,
+    encoder: SparkMsgEncoder,
+    stream:  SparkStreamDecoder,
+}
+
+impl App {
+    async fn request(&mut self, msg: AppToSparkMsg) -> u8 {
+        let sequence = self.encoder.next_sequence();
+        for block in self.encoder.encode(msg).unwrap() {
+            self.link.send(&block).await.unwrap();
+        }
+        sequence
+    }
+
+    async fn next_message(&mut self) -> SparkToAppMsg {
+        let mut buf = [0u8; 64];
+        loop {
+            if let Some(msg) = self.stream.next_message() {
+                return msg.unwrap();
+            }
+            let len = self.link.receive(&mut buf).await.unwrap();
+            self.stream.push(&buf[..len]);
+        }
+    }
+}
+
+// Runs `test` against a Spark 40 until it returns, then drops the link
+fn with_amp<F: Future<Output = ()>>(amp: &SimulatedAmp<LoopbackTransport>, app: LoopbackTransport, test: impl FnOnce(App) -> F) {
+    let app = App { link: app, encoder: SparkMsgEncoder::new(), stream: SparkStreamDecoder::new() };
+    block_on(async {
+        let (result, _) = join(amp.run(), async {
+            test(app).await;
+            amp.transport().disconnect();
+        })
+        .await;
+        assert_eq!(result, Ok(()));
+    });
+}
+
+fn spark_40() -> (SimulatedAmp<LoopbackTransport>, LoopbackTransport) {
+    let (app, amp) = LoopbackTransport::pair();
+    (SimulatedAmp::new(amp, SimIdentity::spark_40(), stored_presets()), app)
+}
+
+#[test]
+fn answers_the_connection_flow() {
+    let (amp, link) = spark_40();
+    let identity = SimIdentity::spark_40();
+
+    with_amp(&amp, link, |mut app| async move {
+        let sequence = app.request(AppToSparkMsg::GetAmpName).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::AmpName { sequence, name: identity.name });
+
+        let sequence = app.request(AppToSparkMsg::GetSerialNumber).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::SerialNumber { sequence, serial: identity.serial });
+
+        let sequence = app.request(AppToSparkMsg::GetFirmwareVersion).await;
+        assert_eq!(
+            app.next_message().await,
+            SparkToAppMsg::FirmwareVersion { sequence, version: identity.firmware }
+        );
+
+        app.request(AppToSparkMsg::GetPresetChecksums).await;
+        match app.next_message().await {
+            SparkToAppMsg::PresetChecksums { checksums, .. } => assert_eq!(checksums.len(), 4),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let sequence = app.request(AppToSparkMsg::GetSelectedHardwarePreset).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::SelectedHardwarePreset { sequence, preset: 1 });
+
+        let sequence = app.request(AppToSparkMsg::GetCurrentPreset).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::Preset { sequence, preset: stored_presets().remove(0) });
+    });
+    assert_eq!(amp.requests_handled(), 6);
+}
+
+#[test]
+fn switches_and_stores_presets() {
+    let (amp, link) = spark_40();
+    let mut edited = Preset { slot: 2, name: "Edited".into(), ..preset() };
+    edited.pedals[2].enabled = false;
+    let stored = edited.clone();
+
+    with_amp(&amp, link, |mut app| async move {
+        let sequence = app.request(AppToSparkMsg::SetHardwarePreset(2)).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::Ack { sequence, sub_command: 0x38 });
+
+        let sequence = app.request(AppToSparkMsg::SendPreset(stored)).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::Ack { sequence, sub_command: 0x01 });
+
+        let sequence = app.request(AppToSparkMsg::ToggleEffect { effect_id: "Booster".into(), enabled: false }).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::Ack { sequence, sub_command: 0x15 });
+
+        let sequence = app.request(AppToSparkMsg::SetParameter {
+            effect_id:   "RolandJC120".into(),
+            param_index: 1,
+            value:       0.9,
+        }).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::Ack { sequence, sub_command: 0x04 });
+    });
+
+    assert_eq!(amp.hardware_preset(), 2);
+    assert_eq!(amp.stored_preset(2), Some(edited));
+
+    let current = amp.current_preset();
+    assert_eq!(current.name, "Preset 2");
+    assert!(!current.pedals[2].enabled);
+    assert_eq!(current.pedals[3].parameters[1], 0.9);
+}
+
+#[test]
+fn plays_back_scripted_events() {
+    let (amp, link) = spark_40();
+    let knob = SparkToAppMsg::ParameterChanged { sequence: 0x20, effect_id: "Booster".into(), param_index: 0, value: 0.6 };
+    let button = SparkToAppMsg::HardwarePresetChanged { sequence: 0x21, preset: 3 };
+    amp.script(ScriptedEvent { after_requests: 1, event: knob.clone() });
+    amp.script(ScriptedEvent { after_requests: 1, event: button.clone() });
+
+    with_amp(&amp, link, |mut app| async move {
+        let sequence = app.request(AppToSparkMsg::SetTuner(true)).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::Ack { sequence, sub_command: 0x65 });
+        assert_eq!(app.next_message().await, knob);
+        assert_eq!(app.next_message().await, button);
+    });
+
+    assert!(amp.tuner_enabled());
+    assert_eq!(amp.hardware_preset(), 3);
+    assert_eq!(amp.current_preset().name, "Preset 3");
+}
+
+#[test]
+fn ignores_bad_requests() {
+    let (amp, link) = spark_40();
+
+    with_amp(&amp, link, |mut app| async move {
+        // A preset the Spark 40 doesn't have, an opcode it doesn't know and
+        // a corrupt block, none of which get a reply
+        app.request(AppToSparkMsg::SetHardwarePreset(7)).await;
+        app.request(AppToSparkMsg::Unknown { command: 0x02, sub_command: 0x99, payload: vec![] }).await;
+
+        let mut corrupt = SparkMsgEncoder::new().encode(AppToSparkMsg::GetAmpName).unwrap().remove(0);
+        corrupt[19] ^= 0x01;
+        app.link.send(&corrupt).await.unwrap();
+
+        let sequence = app.request(AppToSparkMsg::GetSelectedHardwarePreset).await;
+        assert_eq!(app.next_message().await, SparkToAppMsg::SelectedHardwarePreset { sequence, preset: 1 });
+    });
+    assert_eq!(amp.requests_handled(), 3);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::preset;
+use spark_protocol::message::*;
+use spark_protocol::stream::SparkStreamDecoder;
+
+fn amp_blocks(msg: SparkToAppMsg) -> Vec<u8> {
+    SparkMsgEncoder::<SparkToAppMsg>::default().encode(msg).unwrap().concat()
+}
+
+fn decode_all<M: SparkMessage>(stream: &mut SparkStreamDecoder<M>, bytes: &[u8], piece: usize) -> Vec<Result<M, DecodeError>> {
+    let mut msgs = Vec::new();
+    for data in bytes.chunks(piece) {
+        stream.push(data);
+        while let Some(msg) = stream.next_message() {
+            msgs.push(msg);
+        }
+    }
+    msgs
+}
+
+#[test]
+fn decodes_messages_split_anywhere() {
+    let name  = SparkToAppMsg::AmpName { sequence: 1, name: "Spark GO".into() };
+    let chain = SparkToAppMsg::Preset { sequence: 2, preset: preset() };
+    let knob  = SparkToAppMsg::ParameterChanged { sequence: 3, effect_id: "Twin".into(), param_index: 1, value: 0.5 };
+
+    let mut bytes = amp_blocks(name.clone());
+    bytes.extend(amp_blocks(chain.clone()));
+    bytes.extend(amp_blocks(knob.clone()));
+
+    for piece in 1..=bytes.len() {
+        let mut stream = SparkStreamDecoder::new();
+        assert_eq!(
+            decode_all(&mut stream, &bytes, piece),
+            [Ok(name.clone()), Ok(chain.clone()), Ok(knob.clone())],
+            "split into {} byte pieces", piece
+        );
+        assert_eq!(stream.skipped_bytes(), 0);
+    }
+}
+
+#[test]
+fn recovers_from_corruption() {
+    let first  = SparkToAppMsg::HardwarePresetChanged { sequence: 1, preset: 2 };
+    let second = SparkToAppMsg::EffectToggled { sequence: 2, effect_id: "Booster".into(), enabled: true };
+    let third  = SparkToAppMsg::TunerReading { sequence: 3, note: 4, offset: 0.5 };
+
+    let mut corrupt = amp_blocks(second.clone());
+    corrupt[22] ^= 0x01;
+
+    let mut bytes = vec![0x00, 0x13, 0x01, 0xFE];       // junk and a stray partial magic
+    bytes.extend(amp_blocks(first.clone()));
+    bytes.extend(&amp_blocks(third.clone())[..12]);      // cut off mid-header
+    bytes.extend(corrupt);
+    bytes.extend([0xF7, 0xF7]);
+    bytes.extend(amp_blocks(third.clone()));
+
+    let mut stream = SparkStreamDecoder::new();
+    assert_eq!(
+        decode_all(&mut stream, &bytes, 5),
+        [
+            Ok(first),
+            Err(DecodeError::BadChecksum { command: 0x03, sub_command: 0x15 }),
+            Ok(third),
+        ]
+    );
+    assert_eq!(stream.skipped_bytes(), 4 + 12 + 2);
+    assert_eq!(stream.diagnostics(), DecoderDiagnostics { messages: 2, checksum_errors: 1, other_errors: 0 });
+}
+
+#[test]
+fn splits_a_two_way_capture() {
+    let request = AppToSparkMsg::GetAmpName;
+    let reply   = SparkToAppMsg::AmpName { sequence: 0, name: "Spark 2".into() };
+
+    let mut capture = SparkMsgEncoder::new().encode(request.clone()).unwrap().concat();
+    capture.extend(amp_blocks(reply.clone()));
+
+    let mut from_app = SparkStreamDecoder::<AppToSparkMsg>::default();
+    let mut from_amp = SparkStreamDecoder::new();
+    assert_eq!(decode_all(&mut from_app, &capture, 16), [Ok(request)]);
+    assert_eq!(decode_all(&mut from_amp, &capture, 16), [Ok(reply)]);
+}
@@ -0,0 +1,21 @@
+use spark_protocol::message::*;
+use spark_protocol::tracker::*;
+
+#[test]
+fn matches_replies_by_command() {
+    let mut tracker = RequestTracker::new(1000, 2);
+    tracker.track(5, AppToSparkMsg::SetHardwarePreset(2), Vec::new(), 0);
+    tracker.track(6, AppToSparkMsg::GetSelectedHardwarePreset, Vec::new(), 0);
+
+    // Events carry the same sub-commands as our commands
+    assert!(tracker.on_message(&SparkToAppMsg::HardwarePresetChanged { sequence: 5, preset: 2 }).is_none());
+    assert!(tracker.on_message(&SparkToAppMsg::Ack { sequence: 6, sub_command: 0x10 }).is_none());
+    assert!(tracker.is_pending(5) && tracker.is_pending(6));
+
+    let result = tracker.on_message(&SparkToAppMsg::Ack { sequence: 5, sub_command: 0x38 }).unwrap();
+    assert_eq!((result.sequence, result.request), (5, AppToSparkMsg::SetHardwarePreset(2)));
+
+    let reply = SparkToAppMsg::SelectedHardwarePreset { sequence: 6, preset: 2 };
+    assert_eq!(tracker.on_message(&reply).unwrap().result, Ok(reply));
+    assert!(!tracker.is_pending(5) && !tracker.is_pending(6));
+}
@@ -0,0 +1,64 @@
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+use spark_protocol::message::*;
+use spark_protocol::stream::SparkStreamDecoder;
+use spark_protocol::transport::*;
+
+#[test]
+fn loopback_carries_blocks_both_ways() {
+    let (app, amp) = LoopbackTransport::pair();
+    let mut encoder = SparkMsgEncoder::new();
+    let mut requests = SparkStreamDecoder::<AppToSparkMsg>::default();
+
+    block_on(async {
+        let (received, _) = join(
+            async {
+                let mut buf = [0u8; 16];
+                loop {
+                    let len = amp.receive(&mut buf).await.unwrap();
+                    requests.push(&buf[..len]);
+                    if let Some(msg) = requests.next_message() {
+                        return msg;
+                    }
+                }
+            },
+            async {
+                for block in encoder.encode(AppToSparkMsg::SetTuner(true)).unwrap() {
+                    app.send(&block).await.unwrap();
+                }
+            },
+        )
+        .await;
+        assert_eq!(received, Ok(AppToSparkMsg::SetTuner(true)));
+
+        amp.send(&[0x01, 0x02]).await.unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(app.receive(&mut buf).await, Ok(2));
+        assert_eq!(buf[..2], [0x01, 0x02]);
+    });
+}
+
+#[test]
+fn loopback_disconnects_both_ends() {
+    let (app, amp) = LoopbackTransport::pair();
+    assert_eq!(app.link_state(), LinkState::Connected);
+
+    block_on(async {
+        amp.send(&[0xAA]).await.unwrap();
+        let (received, _) = join(
+            async {
+                let mut buf = [0u8; 4];
+                let first = app.receive(&mut buf).await;
+                (first, app.receive(&mut buf).await)
+            },
+            async { amp.disconnect() },
+        )
+        .await;
+
+        // Bytes sent before the drop still arrive
+        assert_eq!(received, (Ok(1), Err(LoopbackError::Disconnected)));
+        assert_eq!(app.send(&[0x00]).await, Err(LoopbackError::Disconnected));
+    });
+    assert_eq!(app.link_state(), LinkState::Disconnected);
+    assert_eq!(amp.link_state(), LinkState::Disconnected);
+}